@@ -0,0 +1,117 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::BufReader,
+    panic::{self, AssertUnwindSafe},
+};
+use ablf::BlfFile;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use clap::Parser;
+use parquet2peak::{
+    Error,
+    blf::{object_timestamp, object_type_name},
+};
+
+#[derive(Parser, Debug)]
+#[command(author, version, about)]
+struct Args {
+    /// Blf file
+    #[arg(short, long)]
+    file: String,
+}
+
+fn main() {
+    if let Err(error) = run(Args::parse()) {
+        eprintln!("{}", error);
+        std::process::exit(1);
+    }
+}
+
+/// Windows SYSTEMTIME: year, month, day of week, day, hour, minute, second, milliseconds
+fn system_time(st: &[u16; 8]) -> Option<NaiveDateTime> {
+    NaiveDate::from_ymd_opt(i32::from(st[0]), u32::from(st[1]), u32::from(st[3]))?
+        .and_hms_milli_opt(u32::from(st[4]), u32::from(st[5]), u32::from(st[6]), u32::from(st[7]))
+}
+
+/// Logging application of the BLF header, as numbered by Vector
+fn application_name(id: u8) -> &'static str {
+    match id {
+        1 => "CANalyzer",
+        2 => "CANoe",
+        3 => "CANstress",
+        4 => "CANlog",
+        5 => "CANape",
+        6 => "CANcaseXL log",
+        7 => "Vector Logger Configurator",
+        200 => "Porsche Logger",
+        201 => "CAETEC Logger",
+        202 => "Vector Network Simulator",
+        203 => "IPETRONIK logger",
+        204 => "RT PK",
+        205 => "PikeTec",
+        206 => "Sparks",
+        _ => "unknown",
+    }
+}
+
+fn run(args: Args) -> Result<(), Error> {
+    let file = File::open(&args.file).map_err(|error| Error::file(&args.file, error))?;
+    // the header size field is private in ablf
+    let size = file.metadata().map_err(|error| Error::file(&args.file, error))?.len();
+    let blf = match BlfFile::from_reader(BufReader::new(file)) {
+        Ok(b) => b,
+        Err((error, _)) => return Err(Error::Blf(error.to_string())),
+    };
+    let stats = &blf.file_stats;
+    let format_time = |time: Option<NaiveDateTime>| time.map_or("-".to_string(), |time| time.to_string());
+    let (major, minor, build) = stats.application_version;
+
+    println!("File: {}", args.file);
+    println!("Size: {} bytes", size);
+    println!("API version: {}", stats.api_version);
+    println!("Application: {} ({}) {}.{}.{}", application_name(stats.application_id), stats.application_id, major,
+             minor, build);
+    println!("Measurement start: {}", format_time(stats.measurement_start_time()));
+    println!("Last object time: {}", format_time(system_time(&stats.last_object_time)));
+    println!("Objects: {}", stats.object_count);
+
+    let declared = stats.object_count;
+    let start_timestamp = Utc.from_utc_datetime(&stats.measurement_start_time().unwrap_or_default());
+    let mut types: BTreeMap<String, usize> = BTreeMap::new();
+    let mut read = 0;
+    let mut first_ts: Option<i64> = None;
+    let mut last_ts: Option<i64> = None;
+    let mut objects = blf.into_iter();
+    // a truncated or malformed object panics inside the reader, the counts so far are still printed
+    let complete = loop {
+        match panic::catch_unwind(AssertUnwindSafe(|| objects.next())) {
+            Ok(Some(object)) => {
+                read += 1;
+                *types.entry(object_type_name(&object.data)).or_insert(0) += 1;
+                if let Some(ts) = object_timestamp(&object.data, start_timestamp) {
+                    first_ts = Some(first_ts.map_or(ts, |first| first.min(ts)));
+                    last_ts = Some(last_ts.map_or(ts, |last| last.max(ts)));
+                }
+            }
+            Ok(None) => break true,
+            Err(_) => break false,
+        }
+    };
+
+    let format_ts = |ts: Option<i64>| ts.map_or("-".to_string(), |ns| DateTime::from_timestamp_nanos(ns).to_rfc3339());
+    println!("First frame: {}", format_ts(first_ts));
+    println!("Last frame: {}", format_ts(last_ts));
+    println!("Object types:");
+    for (name, count) in &types {
+        println!("  {:<32}{:>12}", name, count);
+    }
+
+    if !complete {
+        return Err(Error::Blf(format!("unreadable object after {} of {}", read, declared)));
+    }
+    if read != declared as usize {
+        eprintln!("Warning: read {} objects, the header declares {}", read, declared);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,857 @@
+use std::{
+    borrow::Cow,
+    collections::BTreeSet,
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Cursor, Read, Seek, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+use arrow::record_batch::RecordBatch;
+use chrono::{DateTime, NaiveDateTime};
+use clap::{Parser, builder::BoolishValueParser};
+use memmap2::Mmap;
+use serde::Serialize;
+use parquet2peak::{
+    ConvertOptions, ConvertSummary, DlcMismatch, Error, Format, Monotonic, PercentOf, Protocol, blfs_to_parquet,
+    blfs_to_parquet_by_channel, count_matched,
+    dbc::Dbc,
+    ids::{IdFileArgs, parse_id},
+    j1939::parse_pgn,
+    parquet_writer::{
+        Codec, TsType, build_error_batch, build_event_batch, build_lin_batch, build_statistic_batch, error_schema,
+        event_schema, lin_schema, new_writer, statistic_schema,
+    },
+};
+
+/// Exit code of a conversion stopped by a corrupt or truncated BLF, or with skipped inputs
+const EXIT_PARTIAL: i32 = 2;
+/// Input read from stdin (eg: unzip -p log.zip trace.blf | blf2parquet -i - -o out.parquet)
+const STDIN: &str = "-";
+/// Output written to stdout (eg: blf2parquet -i - -o - < trace.blf > out.parquet)
+const STDOUT: &str = "-";
+
+/// Sidecar files named on the command line, {stem} already replaced
+struct SidecarPaths {
+    lin: Option<PathBuf>,
+    report: Option<PathBuf>,
+}
+
+/// Conversion metrics written by --statistics-output
+#[derive(Serialize)]
+struct ConversionReport<'a> {
+    inputs: &'a [String],
+    output: String,
+    objects: u32,
+    other_channel: usize,
+    rows: usize,
+    /// Nanoseconds since Unix epoch, None without kept frames
+    first_ts: Option<i64>,
+    last_ts: Option<i64>,
+    unique_ids: usize,
+    ids: &'a BTreeSet<u32>,
+    elapsed_seconds: f64,
+}
+
+/// Set when the output goes to stdout, the messages are then printed to stderr
+static OUTPUT_TO_STDOUT: AtomicBool = AtomicBool::new(false);
+
+/// println! to stdout, or to stderr when stdout carries the output
+macro_rules! report {
+    ($($arg:tt)*) => {
+        if OUTPUT_TO_STDOUT.load(Ordering::Relaxed) {
+            eprintln!($($arg)*);
+        } else {
+            println!($($arg)*);
+        }
+    };
+}
+
+/// BLF input, the parser seeks back over corrupt objects
+trait Input: BufRead + Seek + Send {}
+
+impl<T: BufRead + Seek + Send> Input for T {}
+
+/// Readers, names and total size in bytes of the opened inputs
+type Inputs = (Vec<Box<dyn Input>>, Vec<String>, u64);
+
+fn create_output(path: &Path) -> Result<File, Error> {
+    File::create(path).map_err(|error| Error::file(path, error))
+}
+
+/// <stem>_ch<N>.parquet next to `output`, N the BLF channel of the `channel` column value
+fn channel_path(output: &Path, channel: u16) -> PathBuf {
+    let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+    output.with_file_name(format!("{}_ch{}.parquet", stem, channel + 1))
+}
+
+fn write_parquet(path: &Path, batch: &RecordBatch, opts: &ConvertOptions) -> Result<(), Error> {
+    let mut writer = new_writer(create_output(path)?, batch.schema(), opts.compression, opts.compression_level,
+                                opts.dictionary, opts.batch_size)?;
+
+    writer.write(batch)?;
+
+    // writer must be closed to write footer
+    writer.close()?;
+
+    Ok(())
+}
+
+/// Channel number, None for "all"
+fn parse_channel(input: &str) -> Result<Option<u16>, String> {
+    if input.eq_ignore_ascii_case("all") {
+        return Ok(None);
+    }
+    input.parse::<u16>().map(Some).map_err(|error| error.to_string())
+}
+
+/// `offset:value` payload byte (eg: "0:0x62"), the value hex with 0x or decimal
+fn parse_data_match(input: &str) -> Result<(usize, u8), String> {
+    let (offset, value) = input.split_once(':')
+                               .ok_or_else(|| format!("invalid data match {:?}, expected offset:value", input.trim()))?;
+    let offset = offset.trim().parse::<usize>().map_err(|error| format!("invalid offset {:?}: {}", offset, error))?;
+    let value = value.trim();
+    let byte = match value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")) {
+        Some(hex) => u8::from_str_radix(hex, 16),
+        None => value.parse::<u8>(),
+    };
+    Ok((offset, byte.map_err(|error| format!("invalid byte {:?}: {}", value, error))?))
+}
+
+/// RFC 3339 timestamp to nanoseconds since Unix epoch, UTC when it has no offset
+fn parse_rfc3339(input: &str) -> Result<i64, String> {
+    let dt = match DateTime::parse_from_rfc3339(input) {
+        Ok(dt) => dt.to_utc(),
+        Err(error) => ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f"]
+            .iter()
+            .find_map(|format| NaiveDateTime::parse_from_str(input, format).ok())
+            .map(|dt| dt.and_utc())
+            .ok_or_else(|| format!("invalid date and time {:?} (eg: 2024-06-01T10:15:00Z): {}", input, error))?,
+    };
+    dt.timestamp_nanos_opt().ok_or_else(|| format!("{} is out of range", input))
+}
+
+/// Files, directories (their .blf files) and glob patterns, each sorted by name
+fn expand_inputs(inputs: &[String]) -> Vec<PathBuf> {
+    let is_blf = |path: &Path| path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("blf"));
+    let mut paths = Vec::new();
+
+    for input in inputs {
+        if input == STDIN {
+            paths.push(PathBuf::from(STDIN));
+            continue;
+        }
+        let path = Path::new(input);
+        let mut found: Vec<PathBuf> = if path.is_dir() {
+            match std::fs::read_dir(path) {
+                Ok(entries) => entries.filter_map(Result::ok)
+                                      .map(|entry| entry.path())
+                                      .filter(|path| is_blf(path))
+                                      .collect(),
+                Err(error) => {
+                    report!("Error opening {}: {:?}", input, error);
+                    Vec::new()
+                }
+            }
+        } else if input.contains(['*', '?', '[']) {
+            match glob::glob(input) {
+                Ok(matches) => matches.filter_map(Result::ok).collect(),
+                Err(error) => {
+                    report!("Invalid pattern {}: {}", input, error);
+                    Vec::new()
+                }
+            }
+        } else {
+            vec![path.to_path_buf()]
+        };
+        found.sort();
+        paths.extend(found);
+    }
+
+    paths
+}
+
+/// Replaces {stem} in `template` with the file name of `input` without extension
+fn output_path(template: &str, input: &Path) -> PathBuf {
+    let stem = input.file_stem().unwrap_or_default().to_string_lossy();
+    PathBuf::from(template.replace("{stem}", &stem))
+}
+
+fn print_summary(summary: &ConvertSummary, opts: &ConvertOptions) {
+    report!("Summary:");
+    report!("  objects:        {}", summary.objects);
+    report!("  kept:           {}", summary.rows);
+    report!("  other channel:  {}", summary.other_channel);
+    if !opts.id_masks.is_empty() {
+        report!("  other id:       {} ({} kept by --id-mask)", summary.other_id, summary.masked_id);
+    } else if !opts.include_id.is_empty() || !opts.exclude_id.is_empty() || !opts.pgns.is_empty() {
+        report!("  other id:       {}", summary.other_id);
+    }
+    if opts.min_dlc.is_some() || opts.max_dlc.is_some() {
+        report!("  other dlc:      {}", summary.other_dlc);
+    }
+    if summary.dlc_mismatch > 0 || opts.dlc_mismatch != DlcMismatch::Keep {
+        report!("  dlc mismatch:   {}", summary.dlc_mismatch);
+    }
+    if !opts.data_match.is_empty() {
+        report!("  other data:     {}", summary.other_data);
+    }
+    if opts.on_change {
+        report!("  unchanged:      {}", summary.unchanged);
+    }
+    if opts.min_interval.is_some() {
+        report!("  downsampled:    {}", summary.downsampled);
+    }
+    for (channel, frames) in &summary.channels {
+        report!("  {:<16}{}", format!("channel {}:", channel + 1), frames);
+    }
+    report!("  out of range:   {}", summary.out_of_range);
+    if opts.monotonic == Monotonic::Warn {
+        report!("  backwards ts:   {}", summary.non_monotonic);
+    }
+    report!("  other type:     {}", summary.other_type);
+    report!("  unreadable:     {}", summary.corrupt);
+    if opts.errors {
+        report!("  error frames:   {}", summary.errors.ts.len());
+    }
+    if opts.error_rows {
+        report!("  error rows:     {}", summary.error_rows);
+    }
+    report!("  pipeline:       {:.2?} decoding + {:.2?} writing in {:.2?} ({:.1}x)", summary.decode_time,
+            summary.write_time, summary.pipeline_time, summary.speedup());
+}
+
+/// Maps `file` in memory, None when the filesystem does not support it (eg: network shares)
+fn map_file(file: &File) -> Option<Mmap> {
+    // Safety: the BLF must not be truncated or rewritten while it is converted
+    unsafe { Mmap::map(file) }.ok()
+}
+
+/// Opens `inputs`, memory-mapped with `mmap`, with their names and total size in bytes. Inputs that cannot be
+/// opened are skipped.
+fn open_inputs(inputs: &[PathBuf], mmap: bool) -> Result<Inputs, Error> {
+    let mut readers: Vec<Box<dyn Input>> = Vec::with_capacity(inputs.len());
+    let mut names = Vec::with_capacity(inputs.len());
+    let mut bytes = 0;
+    for input in inputs {
+        if input.as_os_str() == STDIN {
+            // stdin cannot seek, the BLF is read in memory
+            let mut data = Vec::new();
+            match io::stdin().read_to_end(&mut data) {
+                Ok(len) => {
+                    bytes += len as u64;
+                    readers.push(Box::new(Cursor::new(data)));
+                    names.push("stdin".to_string());
+                }
+                Err(error) => report!("Error reading stdin: {:?}", error),
+            }
+            continue;
+        }
+        match File::open(input) {
+            Ok(file) => {
+                bytes += file.metadata().map_or(0, |metadata| metadata.len());
+                match mmap.then(|| map_file(&file)) {
+                    Some(Some(map)) => readers.push(Box::new(Cursor::new(map))),
+                    Some(None) => {
+                        eprintln!("Cannot map {}, falling back to buffered reads", input.display());
+                        readers.push(Box::new(BufReader::new(file)));
+                    }
+                    None => readers.push(Box::new(BufReader::new(file))),
+                }
+                names.push(input.display().to_string());
+            }
+            Err(error) => report!("Error opening {}: {:?}", input.display(), error),
+        }
+    }
+    if readers.is_empty() {
+        return Err(Error::Options("No input could be opened".to_string()));
+    }
+    Ok((readers, names, bytes))
+}
+
+/// Converts `inputs` into `output`, or a file per channel next to it with `split`, with its sidecar files and the
+/// ones of `sidecars`, memory-mapped with `mmap`. With `PercentOf::Matched` the inputs are read a first time to
+/// count the matched frames. Inputs that cannot be opened are skipped, returns false when the output is partial.
+fn convert(inputs: &[PathBuf], output: &Path, sidecars: &SidecarPaths, opts: &ConvertOptions, quiet: bool,
+           mmap: bool, split: bool)
+    -> Result<bool, Error>
+{
+    let start = Instant::now();
+    let mut opts = Cow::Borrowed(opts);
+    if opts.percent_of == PercentOf::Matched {
+        // a first pass counts the matched frames, the inputs are read twice
+        if inputs.iter().any(|input| input.as_os_str() == STDIN) {
+            return Err(Error::Options("--percent-of matched reads the inputs twice, stdin cannot be used"
+                                          .to_string()));
+        }
+        let (readers, _, _) = open_inputs(inputs, mmap)?;
+        let total = count_matched(readers, &opts)?;
+        report!("Matched frames: {}", total);
+        opts.to_mut().matched_frames = Some(total);
+    }
+    let opts = &*opts;
+    let (readers, names, bytes) = open_inputs(inputs, mmap)?;
+    let skipped = inputs.len() - readers.len();
+    let summary = if split {
+        blfs_to_parquet_by_channel(readers, |channel| create_output(&channel_path(output, channel)), opts)?
+    } else {
+        let out_file: Box<dyn Write + Send> = if output.as_os_str() == STDOUT {
+            Box::new(BufWriter::new(io::stdout()))
+        } else {
+            Box::new(create_output(output)?)
+        };
+        blfs_to_parquet(readers, out_file, opts)?
+    };
+    if split {
+        for (channel, frames) in &summary.channels {
+            report!("Channel {}: {} frames to {}", channel + 1, frames, channel_path(output, *channel).display());
+        }
+    }
+
+    if summary.files.len() > 1 {
+        let mut last_ts = None;
+        for file in &summary.files {
+            report!("{}: {} objects, {} frames", names[file.input], file.objects, file.frames);
+            if let (Some(last), Some(first)) = (last_ts, file.first_ts) && first < last {
+                report!("{} overlaps the previous file, their frames are interleaved by timestamp",
+                         names[file.input]);
+            }
+            last_ts = file.last_ts.or(last_ts);
+        }
+    }
+
+    if let Some(read) = summary.truncated {
+        eprintln!("Warning: BLF decoding failed at object {} of {}, output holds the frames read before it",
+                  read + 1, summary.objects);
+    }
+
+    if summary.corrupt > 0 {
+        eprintln!("Warning: skipped {} unreadable objects, output is partial", summary.corrupt);
+    }
+
+    if summary.rows == 0 && !summary.unsupported.is_empty() {
+        let seen: Vec<String> = summary.unsupported.iter()
+                                                   .map(|(name, count)| format!("{} ({})", name, count))
+                                                   .collect();
+        eprintln!("No frames matched, unsupported object types seen: {}", seen.join(", "));
+    }
+
+    let duration = start.elapsed();
+    report!("Read throughput: {:.1} MB/s", bytes as f64 / 1e6 / summary.pipeline_time.as_secs_f64().max(1e-9));
+    if opts.fd && opts.protocol == Protocol::Can {
+        report!("Convert to records {} ({} CAN FD): {:?}", summary.rows, summary.fd_frames, duration);
+    } else {
+        report!("Convert to records {}: {:?}", summary.rows, duration);
+    }
+
+    if summary.limit_reached {
+        report!("Reached --max-frames {}, the rest of the input was not converted", summary.rows);
+    }
+
+    if opts.protocol == Protocol::FlexRay {
+        report!("Skipped FlexRay frames (null or unknown channel): {}", summary.flexray_skipped);
+    }
+
+    if !quiet {
+        print_summary(&summary, opts);
+    }
+
+    if opts.errors {
+        let errors_parquet = output.with_extension("errors.parquet");
+        let error_batch = build_error_batch(&error_schema(opts.ts_type), &summary.errors)?;
+        report!("Error frames {} to {}", error_batch.num_rows(), errors_parquet.display());
+        write_parquet(&errors_parquet, &error_batch, opts)?;
+    }
+
+    if opts.events {
+        let events_parquet = output.with_extension("events.parquet");
+        let event_batch = build_event_batch(&event_schema(opts.ts_type), &summary.events)?;
+        report!("Events {} to {}", event_batch.num_rows(), events_parquet.display());
+        write_parquet(&events_parquet, &event_batch, opts)?;
+    }
+
+    if opts.statistics {
+        let statistics_parquet = output.with_extension("statistics.parquet");
+        let stat_batch = build_statistic_batch(&statistic_schema(opts.ts_type), &summary.statistics)?;
+        report!("Bus statistics {} to {}", stat_batch.num_rows(), statistics_parquet.display());
+        write_parquet(&statistics_parquet, &stat_batch, opts)?;
+    }
+
+    if let Some(lin_parquet) = &sidecars.lin {
+        let lin_batch = build_lin_batch(&lin_schema(opts.ts_type), &summary.lin)?;
+        report!("LIN frames {} to {}", lin_batch.num_rows(), lin_parquet.display());
+        write_parquet(lin_parquet, &lin_batch, opts)?;
+    }
+
+    if let Some(report_json) = &sidecars.report {
+        let metrics = ConversionReport {
+            inputs: &names,
+            output: output.display().to_string(),
+            objects: summary.objects,
+            other_channel: summary.other_channel,
+            rows: summary.rows,
+            first_ts: summary.files.iter().filter_map(|file| file.first_ts).min(),
+            last_ts: summary.files.iter().filter_map(|file| file.last_ts).max(),
+            unique_ids: summary.ids.len(),
+            ids: &summary.ids,
+            elapsed_seconds: start.elapsed().as_secs_f64(),
+        };
+        let mut writer = BufWriter::new(create_output(report_json)?);
+        serde_json::to_writer_pretty(&mut writer, &metrics).map_err(io::Error::from)?;
+        writer.flush()?;
+        report!("Conversion statistics to {}", report_json.display());
+    }
+
+    Ok(skipped == 0 && summary.truncated.is_none() && summary.corrupt == 0)
+}
+
+#[derive(Parser, Debug)]
+#[command(author, version, about)]
+pub struct Args {
+    /// Blf input files, directories or glob patterns (eg: "logs/2024-06-*.blf"), repeatable or comma separated,
+    /// "-" reads a single BLF from stdin
+    #[arg(short, long, required = true, value_delimiter = ',')]
+    input: Vec<String>,
+
+    /// Parquet output file, {stem} (eg: "out/{stem}.parquet") converts each input to its own file,
+    /// "-" writes to stdout
+    #[arg(short, long, required_unless_present = "output_dir")]
+    output: Option<String>,
+
+    /// Directory receiving one file per input, named after it with the extension of --format, created if missing
+    #[arg(long, conflicts_with_all = ["output", "merge"])]
+    output_dir: Option<PathBuf>,
+
+    /// Merge every input in the output file, default unless the output has {stem}
+    #[arg(long, default_value_t = false)]
+    merge: bool,
+
+    /// BLF channel as numbered in CANoe, from 1, repeatable (eg: -c 1 -c 3 or -c 1,3), every channel when omitted
+    /// or "all"
+    #[arg(short, long, value_delimiter = ',', value_parser = parse_channel)]
+    channel: Vec<Option<u16>>,
+
+    /// Deprecated, read --channel from 0 as before (-c 0 is BLF channel 1)
+    #[arg(long, default_value_t = false)]
+    zero_based: bool,
+
+    /// Convert the frames of every channel instead of --channel
+    #[arg(long, default_value_t = false, conflicts_with = "channel")]
+    all_channels: bool,
+
+    /// Write each channel to <OUTPUT stem>_ch<N>.parquet instead of one file, needs every channel
+    #[arg(long, default_value_t = false)]
+    split_channels: bool,
+
+    /// IDs to convert, hex or decimal (eg: "0x123,0x7E0,2024"), every ID when omitted
+    #[arg(long, value_delimiter = ',', value_parser = parse_id)]
+    include_id: Vec<u32>,
+
+    /// IDs left out of the conversion, hex or decimal (eg: "0x0C0"), applied after --include-id
+    #[arg(long, value_delimiter = ',', value_parser = parse_id)]
+    exclude_id: Vec<u32>,
+
+    #[command(flatten)]
+    id_file: IdFileArgs,
+
+    /// Mask of an ID filter, repeatable, each paired with the --id-match at the same position (eg: "0x00FF0000")
+    #[arg(long, value_parser = parse_id)]
+    id_mask: Vec<u32>,
+
+    /// Value the masked ID must equal, repeatable (eg: "0x00EF0000"), pairs are OR'ed with --include-id
+    #[arg(long, value_parser = parse_id)]
+    id_match: Vec<u32>,
+
+    /// J1939 PGNs to convert, hex or decimal (eg: "0xFEF1,0xF004"), extended CAN frames only, AND'ed with the IDs
+    #[arg(long, value_delimiter = ',', value_parser = parse_pgn)]
+    pgn: Vec<u32>,
+
+    /// Payload byte a frame must hold, as offset:value, repeatable and AND'ed (eg: "0:0x62")
+    #[arg(long, value_parser = parse_data_match)]
+    data_match: Vec<(usize, u8)>,
+
+    /// Keep only frames with at least this many data bytes
+    #[arg(long, value_parser = clap::value_parser!(u8).range(..=64))]
+    min_dlc: Option<u8>,
+
+    /// Keep only frames with at most this many data bytes, up to 64 for CAN FD
+    #[arg(long, value_parser = clap::value_parser!(u8).range(..=64))]
+    max_dlc: Option<u8>,
+
+    /// Frames whose data length differs from their DLC: kept as logged, skipped, or truncated (zero-padded) to the DLC
+    #[arg(long, value_enum, default_value_t = DlcMismatch::Keep)]
+    dlc_mismatch: DlcMismatch,
+
+    /// Bus protocol to convert (flexray and ethernet use their own schema)
+    #[arg(short, long, value_enum, default_value_t = Protocol::Can)]
+    protocol: Protocol,
+
+    /// Output format of the frames, csv writes ts,id,data_hex lines (CAN and LIN only), arrow-ipc a Feather v2 file
+    #[arg(long, value_enum, default_value_t = Format::Parquet)]
+    format: Format,
+
+    /// Start percentage
+    #[arg(short, long, default_value_t = 0.0)]
+    start_percentage: f64,
+
+    /// End percentage
+    #[arg(short, long, default_value_t = 100.0)]
+    end_percentage: f64,
+
+    /// What the percentages count: every BLF object, or the frames kept by the other filters (reads the input twice)
+    #[arg(long, value_enum, default_value_t = PercentOf::Objects)]
+    percent_of: PercentOf,
+
+    /// Absolute start time, RFC 3339 (eg: 2024-01-15T10:30:00Z), intersected with the percentage window
+    #[arg(long, visible_alias = "from", value_parser = parse_rfc3339)]
+    start_time: Option<i64>,
+
+    /// Absolute end time, RFC 3339 (eg: 2024-01-15T10:30:05Z), intersected with the percentage window
+    #[arg(long, visible_alias = "to", value_parser = parse_rfc3339)]
+    end_time: Option<i64>,
+
+    /// Start of the window in seconds from the measurement start, instead of the percentages
+    #[arg(long, conflicts_with_all = ["start_percentage", "end_percentage"])]
+    start_seconds: Option<f64>,
+
+    /// End of the window in seconds from the measurement start, instead of the percentages
+    #[arg(long, conflicts_with_all = ["start_percentage", "end_percentage"])]
+    end_seconds: Option<f64>,
+
+    /// Keep a frame only if its ID was last kept at least this many milliseconds before (eg: 100 for 10 Hz)
+    #[arg(long)]
+    min_interval_ms: Option<u64>,
+
+    /// Keep a frame only if its data differs from the last kept frame of its ID
+    #[arg(long, default_value_t = false)]
+    on_change: bool,
+
+    /// With --on-change, keep an unchanged frame when its ID was last kept at least this many seconds before
+    #[arg(long, requires = "on_change")]
+    on_change_heartbeat: Option<f64>,
+
+    /// Stop once this many frames are kept by the other filters
+    #[arg(long)]
+    max_frames: Option<usize>,
+
+    /// Convert CAN FD frames too (adds is_fd, brs and esi columns)
+    #[arg(long, default_value_t = false)]
+    fd: bool,
+
+    /// Write CAN error frames of every channel to <OUTPUT>.errors.parquet
+    #[arg(long, visible_alias = "include-errors", default_value_t = false)]
+    errors: bool,
+
+    /// Write CAN error frames of the kept channels to the output too, as rows flagged by is_error and error_code
+    #[arg(long, default_value_t = false)]
+    error_rows: bool,
+
+    /// Write AppText and AppTrigger objects to <OUTPUT>.events.parquet
+    #[arg(long, default_value_t = false)]
+    events: bool,
+
+    /// Write CAN bus statistics of every channel to <OUTPUT>.statistics.parquet
+    #[arg(long, default_value_t = false)]
+    statistics: bool,
+
+    /// Write LIN frames of every channel to this parquet file, {stem} is replaced as in --output
+    #[arg(long)]
+    lin_output: Option<String>,
+
+    /// Write conversion metrics (inputs, counts, time span, IDs, elapsed time) to this JSON file, {stem} is
+    /// replaced as in --output
+    #[arg(long)]
+    statistics_output: Option<String>,
+
+    /// Store ts as Float64 seconds instead of Int64 nanoseconds, same as --ts-type float64
+    #[arg(long, default_value_t = false, conflicts_with = "ts_type")]
+    legacy_float_ts: bool,
+
+    /// Type of the ts column: Int64 nanoseconds, Float64 seconds or a UTC Timestamp(Nanosecond)
+    #[arg(long, value_enum, default_value_t = TsType::Int64)]
+    ts_type: TsType,
+
+    /// Rows buffered before a record batch is flushed to the output as one row group
+    #[arg(long, visible_alias = "row-group-size", default_value_t = 100_000)]
+    batch_size: usize,
+
+    /// Parquet compression codec, also used for the sidecar files
+    #[arg(long, value_enum, default_value_t = Codec::Snappy)]
+    compression: Codec,
+
+    /// Compression level: 1-22 for zstd, 0-10 for gzip, 0-11 for brotli (default of the codec when not set)
+    #[arg(long)]
+    compression_level: Option<u32>,
+
+    /// Disable the dictionary encoding of every column, to compare file sizes
+    #[arg(long, default_value_t = false)]
+    no_dict: bool,
+
+    /// Dictionary encoding: on (default) or off, same as --no-dict when off
+    #[arg(long, value_parser = BoolishValueParser::new(), conflicts_with = "no_dict")]
+    dictionary: Option<bool>,
+
+    /// Keep the frames read before a corrupt or truncated object (exit code 2)
+    #[arg(long, default_value_t = false)]
+    best_effort: bool,
+
+    /// Skip up to this many unreadable objects before giving up (exit code 2 when any is skipped)
+    #[arg(long)]
+    max_errors: Option<usize>,
+
+    /// DBC file, adds a Float64 column per signal (CAN only)
+    #[arg(long)]
+    dbc: Option<String>,
+
+    /// Threads decoding the BLF objects, next to the reading and the writing threads (default: free cores)
+    #[arg(long)]
+    decode_threads: Option<usize>,
+
+    /// Print a warning for each frame whose timestamp is lower than the previous one in BLF order, with its index and
+    /// the jump
+    #[arg(long, default_value_t = false)]
+    warn_non_monotonic: bool,
+
+    /// Stop the conversion with an error at the first frame whose timestamp is lower than the previous one
+    #[arg(long, default_value_t = false, conflicts_with = "warn_non_monotonic")]
+    error_non_monotonic: bool,
+
+    /// Memory-map the inputs instead of buffered reads, falls back to buffered reads when mapping fails
+    #[arg(long, default_value_t = false)]
+    mmap: bool,
+
+    /// Do not print the run summary and the progress bar
+    #[arg(short, long, default_value_t = false)]
+    quiet: bool,
+}
+
+/// Runs the conversion and exits with its status code
+pub fn execute(args: Args) {
+    match run(args) {
+        Ok(true) => {}
+        Ok(false) => std::process::exit(EXIT_PARTIAL),
+        Err(error) => {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// BLF channels selected by --channel, numbered from 1 as in CANoe or from 0 with --zero-based, empty for every
+/// channel
+fn blf_channels(args: &Args) -> Result<Vec<u16>, Error> {
+    if args.zero_based {
+        eprintln!("Warning: --zero-based is deprecated and will be removed, --channel is numbered from 1 as in CANoe");
+    } else if args.channel.contains(&Some(0)) {
+        return Err(Error::Options("BLF channels are numbered from 1 as in CANoe, there is no --channel 0 \
+                                   (--zero-based keeps the old numbering)".to_string()));
+    }
+    if args.all_channels || args.channel.contains(&None) {
+        return Ok(Vec::new());
+    }
+    let offset = u16::from(args.zero_based);
+    Ok(args.channel.iter().flatten().map(|channel| channel + offset).collect())
+}
+
+/// Returns false when the output is partial
+fn run(args: Args) -> Result<bool, Error> {
+    let output_parquet = &match (&args.output_dir, &args.output) {
+        (Some(dir), _) => {
+            if args.input.iter().any(|input| input == STDIN) {
+                return Err(Error::Options("--output-dir names the outputs after the inputs, stdin (-) cannot be used"
+                                              .to_string()));
+            }
+            std::fs::create_dir_all(dir).map_err(|error| Error::file(dir, error))?;
+            let extension = match args.format {
+                Format::Parquet => "parquet",
+                Format::Csv => "csv",
+                Format::ArrowIpc => "arrow",
+            };
+            dir.join(format!("{{stem}}.{}", extension)).to_string_lossy().into_owned()
+        }
+        (None, output) => output.clone().unwrap_or_default(),
+    };
+    let one_to_one = output_parquet.contains("{stem}");
+    if output_parquet == STDOUT {
+        // sidecar paths are derived from the output path
+        if args.errors || args.events || args.statistics {
+            return Err(Error::Options("--errors, --events and --statistics need an output file, not stdout (-)"
+                                          .to_string()));
+        }
+        OUTPUT_TO_STDOUT.store(true, Ordering::Relaxed);
+    }
+    if one_to_one && args.merge {
+        return Err(Error::Options("--merge needs a single output file, {stem} is not allowed".to_string()));
+    }
+    if args.id_mask.len() != args.id_match.len() {
+        return Err(Error::Options(format!("--id-mask and --id-match come in pairs, got {} masks and {} values",
+                                          args.id_mask.len(), args.id_match.len())));
+    }
+    if let Some(heartbeat) = args.on_change_heartbeat && !(heartbeat.is_finite() && heartbeat > 0.0) {
+        return Err(Error::Options(format!("--on-change-heartbeat must be a positive number of seconds, got {}",
+                                          heartbeat)));
+    }
+    if let (Some(min), Some(max)) = (args.min_dlc, args.max_dlc) && min > max {
+        return Err(Error::Options(format!("--min-dlc {} is above --max-dlc {}", min, max)));
+    }
+    let channels = blf_channels(&args)?;
+    let (mut include_id, mut exclude_id) = (args.include_id, args.exclude_id);
+    args.id_file.extend(&mut include_id, &mut exclude_id)?;
+    // checked before any input is read, an invalid level would fail each conversion
+    args.compression.compression(args.compression_level).map_err(|error| Error::Options(error.to_string()))?;
+    let dbc = args.dbc.as_ref().map(|path| Dbc::from_file(Path::new(path))).transpose()?;
+    let opts = ConvertOptions {
+        channels,
+        include_id,
+        exclude_id,
+        id_masks: args.id_mask.iter().copied().zip(args.id_match.iter().copied()).collect(),
+        pgns: args.pgn,
+        data_match: args.data_match,
+        min_dlc: args.min_dlc.map(usize::from),
+        max_dlc: args.max_dlc.map(usize::from),
+        dlc_mismatch: args.dlc_mismatch,
+        protocol: args.protocol,
+        format: args.format,
+        start_percentage: args.start_percentage,
+        end_percentage: args.end_percentage,
+        percent_of: args.percent_of,
+        matched_frames: None,
+        start_time: args.start_time,
+        end_time: args.end_time,
+        start_seconds: args.start_seconds,
+        end_seconds: args.end_seconds,
+        min_interval: args.min_interval_ms.map(Duration::from_millis),
+        on_change: args.on_change,
+        on_change_heartbeat: args.on_change_heartbeat.map(Duration::from_secs_f64),
+        max_frames: args.max_frames,
+        fd: args.fd,
+        errors: args.errors,
+        error_rows: args.error_rows,
+        events: args.events,
+        statistics: args.statistics,
+        lin: args.lin_output.is_some(),
+        ts_type: if args.legacy_float_ts { TsType::Float64 } else { args.ts_type },
+        batch_size: args.batch_size,
+        compression: args.compression,
+        compression_level: args.compression_level,
+        dictionary: args.dictionary.unwrap_or(!args.no_dict),
+        best_effort: args.best_effort,
+        max_errors: args.max_errors,
+        dbc,
+        progress: !args.quiet,
+        messages_to_stderr: output_parquet == STDOUT,
+        decode_threads: args.decode_threads,
+        monotonic: if args.error_non_monotonic {
+            Monotonic::Error
+        } else if args.warn_non_monotonic {
+            Monotonic::Warn
+        } else {
+            Monotonic::Off
+        },
+    };
+
+    if args.split_channels && (!opts.channels.is_empty() || output_parquet == STDOUT) {
+        return Err(Error::Options("--split-channels needs every channel (--all-channels) and an output file"
+                                      .to_string()));
+    }
+
+    let start = Instant::now();
+    let inputs = expand_inputs(&args.input);
+    if inputs.is_empty() {
+        return Err(Error::Options("No BLF input found".to_string()));
+    }
+    if inputs.len() > 1 && inputs.iter().any(|input| input.as_os_str() == STDIN) {
+        return Err(Error::Options("stdin (-) must be the only input".to_string()));
+    }
+
+    let mut complete = true;
+    if one_to_one {
+        let mut failed = 0;
+        for input in &inputs {
+            let output = output_path(output_parquet, input);
+            if let Some(parent) = output.parent() {
+                std::fs::create_dir_all(parent).map_err(|error| Error::file(parent, error))?;
+            }
+            let sidecars = SidecarPaths {
+                lin: args.lin_output.as_ref().map(|template| output_path(template, input)),
+                report: args.statistics_output.as_ref().map(|template| output_path(template, input)),
+            };
+            report!("{} -> {}", input.display(), output.display());
+            match convert(std::slice::from_ref(input), &output, &sidecars, &opts, args.quiet, args.mmap,
+                          args.split_channels) {
+                Ok(done) => complete &= done,
+                Err(error) => {
+                    eprintln!("Skipping {}: {}", input.display(), error);
+                    failed += 1;
+                }
+            }
+        }
+        if failed == inputs.len() {
+            std::process::exit(1);
+        }
+        complete &= failed == 0;
+    } else {
+        let sidecars = SidecarPaths {
+            lin: args.lin_output.as_ref().map(PathBuf::from),
+            report: args.statistics_output.as_ref().map(PathBuf::from),
+        };
+        complete = convert(&inputs, Path::new(output_parquet), &sidecars, &opts, args.quiet, args.mmap,
+                           args.split_channels)?;
+    }
+
+    let duration = start.elapsed();
+    report!("Total execution time: {:?}", duration);
+
+    Ok(complete)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, io::Cursor};
+    use clap::Parser;
+    use parquet2peak::{ConvertOptions, blf_to_parquet, blf_writer::BlfWriter};
+    use super::{Args, blf_channels};
+
+    const START: i64 = 1_718_445_005_000_000_000;
+
+    /// One frame on BLF channel 1 and two on channel 2
+    fn fixture() -> Vec<u8> {
+        let mut blf = Cursor::new(Vec::new());
+        let mut writer = BlfWriter::new(&mut blf, START).unwrap();
+        writer.write_can_message(START, 1, 0x100, 0, &[1]).unwrap();
+        writer.write_can_message(START + 1_000, 2, 0x200, 0, &[2]).unwrap();
+        writer.write_can_message(START + 2_000, 2, 0x201, 0, &[3]).unwrap();
+        writer.finish().unwrap();
+        blf.into_inner()
+    }
+
+    fn parse(channel_args: &[&str]) -> Args {
+        let args = ["blf2parquet", "-i", "in.blf", "-o", "out.parquet"].iter().chain(channel_args);
+        Args::try_parse_from(args).unwrap()
+    }
+
+    /// Frames kept by the channel column value
+    fn converted(channel_args: &[&str]) -> BTreeMap<u16, usize> {
+        let opts = ConvertOptions { channels: blf_channels(&parse(channel_args)).unwrap(), ..Default::default() };
+        blf_to_parquet(Cursor::new(fixture()), Vec::new(), &opts).unwrap().channels
+    }
+
+    #[test]
+    fn channel_is_numbered_from_one() {
+        assert_eq!(converted(&["-c", "1"]), BTreeMap::from([(0, 1)]));
+        assert_eq!(converted(&["-c", "2"]), BTreeMap::from([(1, 2)]));
+        assert_eq!(converted(&["-c", "1,2"]), BTreeMap::from([(0, 1), (1, 2)]));
+    }
+
+    #[test]
+    fn zero_based_keeps_the_old_numbering() {
+        assert_eq!(converted(&["--zero-based", "-c", "0"]), BTreeMap::from([(0, 1)]));
+        assert_eq!(converted(&["--zero-based", "-c", "1"]), BTreeMap::from([(1, 2)]));
+    }
+
+    #[test]
+    fn channel_zero_is_rejected() {
+        assert!(blf_channels(&parse(&["-c", "0"])).is_err());
+        assert_eq!(blf_channels(&parse(&["-c", "all"])).unwrap(), Vec::<u16>::new());
+    }
+}
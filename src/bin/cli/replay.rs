@@ -0,0 +1,355 @@
+use std::{
+    fs::{self, File},
+    path::Path,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+use peak_can::{
+    bus::UsbBus,
+    socket::usb::UsbCanSocket,
+};
+use clap::Parser;
+use parquet2peak::{
+    Backend, CanSink, Error,
+    ids::{IdFileArgs, parse_id, parse_id_pair},
+    replay::{
+        BAUDRATES, Direction, ReplayOptions, load_parquet, out_of_order, parse_baudrate, parse_id_map, replay,
+        replay_stats,
+    },
+};
+
+#[derive(Parser, Debug)]
+#[command(author, version, about)]
+pub struct Args {
+    /// File path
+    #[arg(short,long)]
+    file: String,
+
+    /// Number of replays, 0 loops forever
+    #[arg(short = 'n', long, default_value_t = 1)]
+    loop_count: u32,
+
+    /// Deprecated, same as --loop-count 0
+    #[arg(short, long, default_value_t = false, conflicts_with = "loop_count")]
+    loop_forever: bool,
+
+    /// Exclusion ID list, hex or decimal (eg: "0x0A,0x0B,0x1F")
+    #[arg(short, long, value_delimiter = ',', value_parser = parse_id)]
+    exclude_id: Vec<u32>,
+
+    /// Inclusion ID list, hex or decimal, applied before the exclusion list (eg: "0x0A,0x0B,0x1F")
+    #[arg(short, long, value_delimiter = ',', value_parser = parse_id)]
+    include_id: Vec<u32>,
+
+    #[command(flatten)]
+    id_file: IdFileArgs,
+
+    /// CSV file of original_id,new_id hex pairs, IDs are filtered before being remapped
+    #[arg(long)]
+    remap_ids: Option<String>,
+
+    /// ID pairs to rewrite, hex or decimal (eg: "0x100:0x200,0x101:0x201"), replacing the --remap-ids pair of the same
+    /// original ID
+    #[arg(long, value_delimiter = ',', value_parser = parse_id_pair)]
+    remap: Vec<(u32, u32)>,
+
+    /// Replay only this BLF channel, from 1 as in CANoe and blf2parquet, ignored for files without channel column
+    #[arg(short, long)]
+    channel: Option<u16>,
+
+    /// Deprecated, read --channel from 0 as the channel column (-c 0 is BLF channel 1)
+    #[arg(long, default_value_t = false)]
+    zero_based: bool,
+
+    /// Replay only received (rx) or transmitted (tx) frames, ignored for files without dir column
+    #[arg(long, value_enum, default_value_t = Direction::Both)]
+    direction: Direction,
+
+    /// Also send the error frame rows of blf2parquet --error-rows, with the ID and data they recorded
+    #[arg(long, default_value_t = false)]
+    replay_errors: bool,
+
+    /// Replay target
+    #[arg(long, value_enum, default_value_t = Backend::Peak)]
+    backend: Backend,
+
+    /// SocketCAN interface, used with --backend socketcan
+    #[arg(long, default_value = "can0")]
+    interface: String,
+
+    /// Same as --backend socketcan --interface <SOCKETCAN> (eg: "vcan0")
+    #[arg(long, conflicts_with_all = ["backend", "interface"])]
+    socketcan: Option<String>,
+
+    /// Bus USB CAN: from 1 to 16
+    #[arg(short, long, default_value_t = 1)]
+    usb_can_bus: u16,
+
+    /// Bus baudrate in kbit/s: 125, 250, 500 or 1000
+    #[arg(short, long, default_value = "500")]
+    baudrate: String,
+
+    /// Load the file and print timing statistics without opening the bus
+    #[arg(long, default_value_t = false)]
+    dry_run: bool,
+
+    /// Replay speed: 2.0 halves inter-frame delays, 0.5 doubles them
+    #[arg(long, visible_alias = "speed", default_value_t = 1.0)]
+    speed_factor: f64,
+
+    /// Seconds added to every timestamp before replay, may be negative (eg: -3600.0)
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    offset_time: f64,
+
+    /// Longest wait between two frames in milliseconds, unlimited when not set
+    #[arg(long)]
+    max_gap: Option<u64>,
+
+    /// Shortest wait between two frames in microseconds, for controllers failing on back-to-back frames
+    #[arg(long, default_value_t = 0)]
+    min_gap_us: u64,
+
+    /// Most frames per second of an ID, as id=fps, repeatable (eg: "0x123=100"), the frames above it are skipped
+    #[arg(long, value_parser = parse_rate_limit)]
+    rate_limit: Vec<(u32, u32)>,
+
+    /// Times a frame refused by a full transmit queue is sent again, with a short backoff, before giving up
+    #[arg(long, default_value_t = 3)]
+    max_send_retries: u32,
+
+    /// Fail when a timestamp is lower than the one of the previous frame
+    #[arg(long, default_value_t = false, conflicts_with = "sort")]
+    require_sorted: bool,
+
+    /// Sort the frames by timestamp before replay, frames with the same timestamp keep their order
+    #[arg(long, default_value_t = false)]
+    sort: bool,
+
+    /// First frame to replay, 0-based index among the frames kept by the filters
+    #[arg(long, default_value_t = 0)]
+    start_index: usize,
+
+    /// Frame where the replay stops, excluded, until the last frame when not set
+    #[arg(long)]
+    end_index: Option<usize>,
+
+    /// Replay at most this many frames, counted after the filters and the index slice
+    #[arg(long)]
+    limit: Option<usize>,
+
+    /// Do not draw the progress bar
+    #[arg(short, long, default_value_t = false)]
+    quiet: bool,
+}
+
+/// `id=fps` pair (eg: "0x123=100"), the rate must be above 0
+fn parse_rate_limit(input: &str) -> Result<(u32, u32), String> {
+    let (id, fps) = input.split_once('=')
+                         .ok_or_else(|| format!("invalid rate limit {:?}, expected id=fps", input.trim()))?;
+    let fps = fps.trim().parse::<u32>().map_err(|error| format!("invalid rate {:?}: {}", fps.trim(), error))?;
+    if fps == 0 {
+        return Err(format!("rate limit of {} must be above 0 frames per second", id.trim()));
+    }
+    Ok((parse_id(id)?, fps))
+}
+
+/// Channel column value of --channel, numbered from 1 as in CANoe or from 0 with --zero-based
+fn column_channel(args: &Args) -> Result<Option<u16>, Error> {
+    if args.zero_based {
+        eprintln!("Warning: --zero-based is deprecated and will be removed, --channel is numbered from 1 as in CANoe");
+        return Ok(args.channel);
+    }
+    match args.channel {
+        Some(0) => Err(Error::Options("BLF channels are numbered from 1 as in CANoe, there is no --channel 0 \
+                                       (--zero-based keeps the old numbering)".to_string())),
+        channel => Ok(channel.map(|channel| channel - 1)),
+    }
+}
+
+/// Runs the replay, exits on error
+pub fn execute(args: Args) {
+    if let Err(error) = run(args) {
+        eprintln!("{}", error);
+        std::process::exit(1);
+    }
+}
+
+fn run(args: Args) -> Result<(), Error> {
+    let file_path = &Path::new(&args.file);
+    let mut remap_id = match &args.remap_ids {
+        Some(path) => parse_id_map(&fs::read_to_string(path).map_err(|error| Error::file(path, error))?)?,
+        None => Default::default(),
+    };
+    remap_id.extend(args.remap.iter().copied());
+    let channel = column_channel(&args)?;
+    let (mut include_id, mut exclude_id) = (args.include_id, args.exclude_id);
+    args.id_file.extend(&mut include_id, &mut exclude_id)?;
+    let opts = ReplayOptions {
+        exclude_id,
+        include_id,
+        remap_id,
+        channel,
+        direction: args.direction,
+        replay_errors: args.replay_errors,
+        loop_count: if args.loop_forever { 0 } else { args.loop_count },
+        speed_factor: args.speed_factor,
+        offset_time: args.offset_time,
+        max_gap: args.max_gap.map(Duration::from_millis),
+        min_gap: Duration::from_micros(args.min_gap_us),
+        rate_limit: args.rate_limit.iter().copied().collect(),
+        max_send_retries: args.max_send_retries,
+        progress: !args.quiet,
+        stop: Arc::new(AtomicBool::new(false)),
+    };
+    if args.end_index.is_some_and(|end| end <= args.start_index) {
+        return Err(Error::Options(format!("--end-index {} must be greater than --start-index {}",
+                                          args.end_index.unwrap_or_default(), args.start_index)));
+    }
+    if args.loop_forever {
+        eprintln!("Warning: --loop-forever is deprecated, use --loop-count 0");
+    }
+    let usb_can_bus = UsbBus::try_from(args.usb_can_bus).unwrap_or_else(|_| {
+        eprintln!("Invalid can bus resetting to USB1!");
+        UsbBus::USB1
+    });
+    let baudrate = parse_baudrate(&args.baudrate).unwrap_or_else(|| {
+        let supported: Vec<String> = BAUDRATES.iter().map(|(rate, _)| rate.to_string()).collect();
+        eprintln!("Invalid baudrate {:?}, supported values (kbit/s): {}", args.baudrate,
+                  supported.join(", "));
+        std::process::exit(1);
+    });
+
+    if opts.speed_factor.is_nan() || opts.speed_factor <= 0.0 {
+        eprintln!("Invalid speed factor {}, it must be greater than 0", opts.speed_factor);
+        std::process::exit(1);
+    }
+    if opts.speed_factor > 100.0 {
+        eprintln!("Warning: at speed factor {} USB latency will dominate the frame timing",
+                  opts.speed_factor);
+    }
+
+    if !opts.exclude_id.is_empty() {
+        println!("Apply filter: {:?}", opts.exclude_id);
+    }
+    if !opts.include_id.is_empty() {
+        println!("Apply include filter: {:?}", opts.include_id);
+    }
+    if !opts.remap_id.is_empty() {
+        println!("Remapping {} IDs", opts.remap_id.len());
+    }
+
+    let start = Instant::now();
+    // Apri il file Parquet
+    let file = File::open(file_path).map_err(|error| Error::file(file_path, error))?;
+    let (mut content, elem) = load_parquet(file, &opts)?;
+    let unsorted = out_of_order(&content);
+    if unsorted > 0 {
+        if args.require_sorted {
+            return Err(Error::Options(format!("{} frames have a timestamp lower than the previous one, \
+                                               replay them with --sort", unsorted)));
+        }
+        eprintln!("Warning: {} out-of-order timestamp pairs{}", unsorted,
+                  if args.sort { ", sorting" } else { ", sent without waiting (see --sort)" });
+        if args.sort {
+            content.sort_by(|a, b| a.ts.total_cmp(&b.ts));
+        }
+    }
+    // indexes count the frames left by the filters, not the rows of the file
+    content.truncate(args.end_index.unwrap_or(content.len()));
+    content.drain(..args.start_index.min(content.len()));
+    if let Some(limit) = args.limit {
+        content.truncate(limit);
+    }
+    let felem = content.len();
+
+    let duration = start.elapsed();
+    println!("Loading data ({} of {}) from {:?}: {:?}", felem, elem, file_path,
+             duration);
+
+    if args.dry_run {
+        let stats = replay_stats(&content);
+        let gap = |value: Option<f64>| value.map_or("-".to_string(), |gap| format!("{:.6}s", gap));
+        println!("Frames: {}", stats.frames);
+        println!("Duration: {:.6}s", stats.span);
+        println!("Gap min/max/mean: {} / {} / {}", gap(stats.min_gap), gap(stats.max_gap),
+                 gap(stats.mean_gap));
+        println!("IDs ({}):", stats.ids.len());
+        for (id, count) in &stats.ids {
+            println!("  0x{:X}: {}", id, count);
+        }
+        return Ok(());
+    }
+
+    let (backend, interface) = match args.socketcan {
+        Some(interface) => (Backend::SocketCan, interface),
+        None => (args.backend, args.interface),
+    };
+    let (sink, bus): (Box<dyn CanSink>, String) = match backend {
+        Backend::Peak => {
+            // the socket takes the baudrate, which is not Copy
+            let bus = format!("{:?}, Baudrate:{:?}", usb_can_bus, baudrate);
+            match UsbCanSocket::open(usb_can_bus, baudrate) {
+                Ok(socket) => (Box::new(socket), bus),
+                Err(err) => return Err(Error::Bus(format!("unable to open USB socket: {:?}", err))),
+            }
+        },
+        #[cfg(target_os = "linux")]
+        Backend::SocketCan => match parquet2peak::sink::open_socketcan(&interface) {
+            Ok(socket) => (Box::new(socket), interface.clone()),
+            Err(err) => return Err(Error::Bus(format!("unable to open {}: {}", interface, err))),
+        },
+        #[cfg(not(target_os = "linux"))]
+        Backend::SocketCan => {
+            eprintln!("The socketcan backend is only available on Linux");
+            std::process::exit(1);
+        }
+    };
+
+    let loops = if opts.loop_count == 0 { "forever".to_string() } else { opts.loop_count.to_string() };
+    let limit = args.limit.map_or(String::new(), |limit| format!(", limit {}", limit));
+    println!("Starting simulation of {} frames{} (loop:{}, Bus:{}, Speed:{}x, Offset:{}s)",
+             content.len(), limit, loops, bus, opts.speed_factor, opts.offset_time);
+
+    // the first Ctrl+C (or SIGTERM) stops after the current frame, a second one exits at once
+    let stop = Arc::clone(&opts.stop);
+    let handler = ctrlc::set_handler(move || {
+        if stop.swap(true, Ordering::Relaxed) {
+            std::process::exit(130);
+        }
+    });
+    if let Err(error) = handler {
+        eprintln!("Warning: cannot handle Ctrl+C, the replay will not be stopped cleanly: {}", error);
+    }
+    replay(&content, sink.as_ref(), &opts)?;
+    println!("Exit!!!");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+    use super::{Args, column_channel};
+
+    fn parse(channel_args: &[&str]) -> Args {
+        let args = ["parquet2peak", "-f", "in.parquet"].iter().chain(channel_args);
+        Args::try_parse_from(args).unwrap()
+    }
+
+    #[test]
+    fn channel_is_numbered_from_one() {
+        assert_eq!(column_channel(&parse(&["-c", "1"])).unwrap(), Some(0));
+        assert_eq!(column_channel(&parse(&["-c", "3"])).unwrap(), Some(2));
+        assert_eq!(column_channel(&parse(&[])).unwrap(), None);
+        assert!(column_channel(&parse(&["-c", "0"])).is_err());
+    }
+
+    #[test]
+    fn zero_based_keeps_the_old_numbering() {
+        assert_eq!(column_channel(&parse(&["--zero-based", "-c", "0"])).unwrap(), Some(0));
+        assert_eq!(column_channel(&parse(&["--zero-based", "-c", "2"])).unwrap(), Some(2));
+    }
+}
@@ -0,0 +1,131 @@
+use std::{
+    fs::File,
+    time::Instant,
+};
+use arrow::{
+    array::AsArray,
+    datatypes::{Float64Type, Int64Type, TimestampNanosecondType, UInt8Type, UInt16Type, UInt32Type},
+    record_batch::RecordBatch,
+};
+use clap::Parser;
+use parquet::{arrow::arrow_reader::ParquetRecordBatchReaderBuilder, errors::ParquetError};
+use parquet2peak::{
+    Error,
+    ids::{id_allowed, parse_id},
+    isotp::Reassembler,
+    parquet_writer::{Codec, FrameBuffer, IsoTpMessages, isotp_schema, new_writer},
+};
+
+/// Messages buffered before they are written as one row group
+const BATCH_MESSAGES: usize = 10_000;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about)]
+struct Args {
+    /// CAN parquet file, as written by blf2parquet, in timestamp order
+    #[arg(short, long)]
+    file: String,
+
+    /// Parquet output with ts_start, ts_end, channel, id, payload and length columns
+    #[arg(short, long)]
+    output: String,
+
+    /// IDs carrying ISO-TP, hex or decimal (eg: "0x7E0,0x7E8"), every ID when omitted
+    #[arg(short, long, value_delimiter = ',', value_parser = parse_id)]
+    include_id: Vec<u32>,
+
+    /// Parquet compression codec
+    #[arg(long, value_enum, default_value_t = Codec::Snappy)]
+    compression: Codec,
+}
+
+fn main() {
+    if let Err(error) = run(Args::parse()) {
+        eprintln!("{}", error);
+        std::process::exit(1);
+    }
+}
+
+/// ts in nanoseconds, stored as Int64 nanoseconds, a UTC Timestamp or legacy Float64 seconds
+fn ts_column(batch: &RecordBatch) -> Result<Vec<i64>, Error> {
+    let column = batch.column_by_name("ts");
+    if let Some(ns) = column.and_then(|column| column.as_primitive_opt::<Int64Type>()) {
+        return Ok(ns.values().to_vec());
+    }
+    if let Some(ns) = column.and_then(|column| column.as_primitive_opt::<TimestampNanosecondType>()) {
+        return Ok(ns.values().to_vec());
+    }
+    if let Some(seconds) = column.and_then(|column| column.as_primitive_opt::<Float64Type>()) {
+        return Ok(seconds.values().iter().map(|ts| (ts * 1e9).round() as i64).collect());
+    }
+    Err(Error::OpenParquet(ParquetError::General("Column ts must be Int64 nanoseconds, a Timestamp or Float64 seconds"
+                                                     .to_string())))
+}
+
+fn run(args: Args) -> Result<(), Error> {
+    let start = Instant::now();
+    let file = File::open(&args.file).map_err(|error| Error::file(&args.file, error))?;
+    let reader = ParquetRecordBatchReaderBuilder::try_new(file).map_err(Error::OpenParquet)?.build()?;
+    let out_file = File::create(&args.output).map_err(|error| Error::file(&args.output, error))?;
+    let schema = isotp_schema();
+    let mut writer = new_writer(out_file, schema.clone(), args.compression, None, true, BATCH_MESSAGES)?;
+
+    let mut reassembler = Reassembler::default();
+    let mut messages = IsoTpMessages::default();
+    let mut frames = 0;
+    let mut rows = 0;
+
+    for batch in reader {
+        let batch = batch?;
+        let ts = ts_column(&batch)?;
+        let id = batch.column_by_name("id")
+                      .and_then(|column| column.as_primitive_opt::<UInt32Type>())
+                      .ok_or_else(|| Error::OpenParquet(ParquetError::General(
+                          "Column id must be UInt32".to_string())))?;
+        let data = batch.column_by_name("data")
+                        .and_then(|column| column.as_list_opt::<i64>())
+                        .ok_or_else(|| Error::OpenParquet(ParquetError::General(
+                            "Column data must be a list of UInt8".to_string())))?;
+        // files without channel column are reassembled as a single channel
+        let channel = batch.column_by_name("channel").and_then(|column| column.as_primitive_opt::<UInt16Type>());
+        // error frame rows of blf2parquet --error-rows are not part of a transfer
+        let is_error = batch.column_by_name("is_error").and_then(|column| column.as_boolean_opt());
+
+        for (row, &ts) in ts.iter().enumerate() {
+            if is_error.is_some_and(|is_error| is_error.value(row))
+               || !id_allowed(id.value(row), &args.include_id, &[]) {
+                continue;
+            }
+            let bytes = data.value(row);
+            let Some(bytes) = bytes.as_primitive_opt::<UInt8Type>() else {
+                continue;
+            };
+            let channel = channel.map_or(0, |channel| channel.value(row));
+            if let Some(message) = reassembler.push(ts, channel, id.value(row), bytes.values()) {
+                messages.push(message);
+            }
+        }
+        frames += batch.num_rows();
+
+        if messages.len() >= BATCH_MESSAGES {
+            writer.write(&messages.build(&schema)?)?;
+            rows += messages.len();
+            messages.clear();
+        }
+    }
+    if !messages.is_empty() {
+        writer.write(&messages.build(&schema)?)?;
+        rows += messages.len();
+    }
+
+    // writer must be closed to write footer
+    writer.close()?;
+
+    println!("Reassembled {} messages from {} frames to {}: {:?}", rows, frames, args.output, start.elapsed());
+    if reassembler.dropped > 0 || reassembler.incomplete() > 0 {
+        eprintln!("Dropped {} segmented messages, {} incomplete at the end of the file", reassembler.dropped,
+                  reassembler.incomplete());
+    }
+
+    Ok(())
+}
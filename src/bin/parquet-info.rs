@@ -0,0 +1,125 @@
+use std::{
+    collections::BTreeSet,
+    fs::File,
+};
+use arrow::{array::AsArray, datatypes::UInt32Type};
+use chrono::DateTime;
+use clap::Parser;
+use parquet::{
+    arrow::{ProjectionMask, arrow_reader::ParquetRecordBatchReaderBuilder},
+    file::{
+        reader::{FileReader, SerializedFileReader},
+        statistics::Statistics,
+    },
+    schema::printer::print_schema,
+};
+use parquet2peak::Error;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about)]
+struct Args {
+    /// Parquet file, as written by blf2parquet
+    #[arg(short, long)]
+    file: String,
+}
+
+/// Min and max ts in nanoseconds, stored as Int64 nanoseconds, a UTC Timestamp or legacy Float64 seconds
+fn ts_range(statistics: &Statistics) -> Option<(i64, i64)> {
+    match statistics {
+        Statistics::Int64(stats) => Some((*stats.min_opt()?, *stats.max_opt()?)),
+        Statistics::Double(stats) => Some(((stats.min_opt()? * 1e9) as i64, (stats.max_opt()? * 1e9) as i64)),
+        _ => None,
+    }
+}
+
+fn main() {
+    if let Err(error) = run(Args::parse()) {
+        eprintln!("{}", error);
+        std::process::exit(1);
+    }
+}
+
+fn run(args: Args) -> Result<(), Error> {
+    let open = || File::open(&args.file).map_err(|error| Error::file(&args.file, error));
+    let reader = SerializedFileReader::new(open()?).map_err(Error::OpenParquet)?;
+    let metadata = reader.metadata();
+    let file_metadata = metadata.file_metadata();
+
+    println!("File: {}", args.file);
+    println!("Rows: {}", file_metadata.num_rows());
+    println!("Row groups: {}", metadata.num_row_groups());
+    if let Some(created_by) = file_metadata.created_by() {
+        println!("Created by: {}", created_by);
+    }
+    // the arrow schema is an encoded copy of the one printed below
+    let key_values: Vec<_> = file_metadata.key_value_metadata()
+                                          .into_iter()
+                                          .flatten()
+                                          .filter(|kv| kv.key != "ARROW:schema")
+                                          .collect();
+    if !key_values.is_empty() {
+        println!("Metadata:");
+        for kv in key_values {
+            println!("  {}: {}", kv.key, kv.value.as_deref().unwrap_or("-"));
+        }
+    }
+    println!("Schema:");
+    print_schema(&mut std::io::stdout(), file_metadata.schema());
+
+    let mut compressed = 0;
+    let mut uncompressed = 0;
+    let mut ts_min: Option<i64> = None;
+    let mut ts_max: Option<i64> = None;
+    // column paths in schema order, with the encodings used by any row group
+    let mut encodings: Vec<(String, BTreeSet<String>)> = Vec::new();
+    for row_group in metadata.row_groups() {
+        for (index, column) in row_group.columns().iter().enumerate() {
+            compressed += column.compressed_size();
+            uncompressed += column.uncompressed_size();
+            if encodings.len() <= index {
+                encodings.push((column.column_path().string(), BTreeSet::new()));
+            }
+            encodings[index].1.extend(column.encodings().iter().map(|encoding| format!("{:?}", encoding)));
+        }
+        // footer statistics, no need to read the rows
+        let range = row_group.columns()
+                             .iter()
+                             .find(|column| column.column_path().string() == "ts")
+                             .and_then(|column| column.statistics())
+                             .and_then(ts_range);
+        if let Some((min, max)) = range {
+            ts_min = Some(ts_min.map_or(min, |ts| ts.min(min)));
+            ts_max = Some(ts_max.map_or(max, |ts| ts.max(max)));
+        }
+    }
+    let format_ts = |ts: Option<i64>| ts.map_or("-".to_string(), |ns| DateTime::from_timestamp_nanos(ns).to_rfc3339());
+    println!("First ts: {}", format_ts(ts_min));
+    println!("Last ts: {}", format_ts(ts_max));
+    if compressed > 0 {
+        println!("Size: {} bytes compressed, {} uncompressed (ratio {:.2})", compressed, uncompressed,
+                 uncompressed as f64 / compressed as f64);
+    }
+    if !encodings.is_empty() {
+        println!("Encodings:");
+        for (path, column_encodings) in &encodings {
+            println!("  {}: {}", path, column_encodings.iter().cloned().collect::<Vec<_>>().join(", "));
+        }
+    }
+
+    // only the id column is read
+    let builder = ParquetRecordBatchReaderBuilder::try_new(open()?).map_err(Error::OpenParquet)?;
+    let id_column = builder.parquet_schema().columns().iter().position(|column| column.name() == "id");
+    if let Some(index) = id_column {
+        let mask = ProjectionMask::leaves(builder.parquet_schema(), [index]);
+        let mut ids = BTreeSet::new();
+        for batch in builder.with_projection(mask).build()? {
+            let batch = batch?;
+            if let Some(column) = batch.column(0).as_primitive_opt::<UInt32Type>() {
+                ids.extend(column.iter().flatten());
+            }
+        }
+        println!("Unique IDs: {}", ids.len());
+    }
+
+    Ok(())
+}
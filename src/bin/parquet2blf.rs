@@ -0,0 +1,72 @@
+use std::{
+    fs::File,
+    io::BufWriter,
+    time::Instant,
+};
+use clap::Parser;
+use parquet2peak::{
+    Error, ReplayOptions,
+    blf::{CAN_MSG_RTR, CAN_MSG_TX},
+    blf_writer::{BlfWriter, CAN_MSG_EXT},
+    replay::load_parquet,
+};
+
+#[derive(Parser, Debug)]
+#[command(author, version, about)]
+struct Args {
+    /// Parquet input file, as written by blf2parquet
+    #[arg(short, long)]
+    input: String,
+
+    /// Blf output file
+    #[arg(short, long)]
+    output: String,
+}
+
+fn main() {
+    if let Err(error) = run(Args::parse()) {
+        eprintln!("{}", error);
+        std::process::exit(1);
+    }
+}
+
+fn run(args: Args) -> Result<(), Error> {
+    let start = Instant::now();
+    let in_file = File::open(&args.input).map_err(|error| Error::file(&args.input, error))?;
+    let (content, elem) = load_parquet(in_file, &ReplayOptions::default())?;
+    println!("Loading data ({} of {}) from {}: {:?}", content.len(), elem, args.input, start.elapsed());
+
+    let out_file = File::create(&args.output).map_err(|error| Error::file(&args.output, error))?;
+
+    // the BLF stores nanoseconds from the measurement start, the seconds of ts would round them
+    let first = content.iter().map(|frame| frame.ts_ns).min().unwrap_or_default();
+    let mut writer = BlfWriter::new(BufWriter::new(out_file), first)?;
+    let mut skipped = 0;
+
+    for frame in &content {
+        if frame.data.len() > 8 {
+            // CAN FD frames have no CAN_MESSAGE2 representation
+            skipped += 1;
+            continue;
+        }
+        let id = if frame.is_extended() { frame.id | CAN_MSG_EXT } else { frame.id };
+        let mut flags = 0;
+        if frame.dir.is_some_and(|dir| dir != 0) {
+            flags |= CAN_MSG_TX;
+        }
+        if frame.rtr {
+            flags |= CAN_MSG_RTR;
+        }
+        // channel column is 0-based, BLF channels start from 1
+        let channel = frame.channel.unwrap_or_default() + 1;
+        writer.write_can_message(frame.ts_ns, channel, id, flags, &frame.data)?;
+    }
+
+    let objects = writer.finish()?;
+    if skipped > 0 {
+        eprintln!("Skipped CAN FD frames: {}", skipped);
+    }
+    println!("Written {} CAN messages to {}: {:?}", objects, args.output, start.elapsed());
+
+    Ok(())
+}
@@ -0,0 +1,484 @@
+//! Decoding of BLF objects into CAN frames
+
+use std::{borrow::Cow, sync::Once, time::Duration};
+use ablf::ObjectTypes;
+use chrono::{DateTime, Utc};
+
+// CAN_FD_MESSAGE (100) canfdFlags bits
+pub const CAN_FD_EDL: u8 = 0x01;
+pub const CAN_FD_BRS: u8 = 0x02;
+pub const CAN_FD_ESI: u8 = 0x04;
+
+// CAN_FD_MESSAGE_64 (101) flags bits
+pub const CAN_FD64_EDL: u32 = 0x1000;
+pub const CAN_FD64_BRS: u32 = 0x2000;
+pub const CAN_FD64_ESI: u32 = 0x4000;
+
+pub const CAN_ID_MASK: u32 = 0x1FFFFFFF;
+/// CAN_MESSAGE, CAN_MESSAGE2 and CAN FD id bit of extended frames
+pub const CAN_MSG_EXT: u32 = 0x80000000;
+
+// CAN_MESSAGE, CAN_MESSAGE2 and CAN_FD_MESSAGE flags bits
+pub const CAN_MSG_TX: u8 = 0x01;
+pub const CAN_MSG_RTR: u8 = 0x80;
+// CAN_FD_MESSAGE_64 flags bit
+pub const CAN_FD64_RTR: u32 = 0x0010;
+
+// object header flags bits, the unit of the object timestamp
+pub const OBJ_FLAG_TIME_MASK: u32 = 0x03;
+pub const OBJ_FLAG_TIME_TEN_MICS: u32 = 0x01;
+pub const OBJ_FLAG_TIME_ONE_NANS: u32 = 0x02;
+
+// FLEXRAY_RCVMESSAGE channelMask and frameFlags bits
+pub const FR_CHANNEL_A: u16 = 0x01;
+pub const FR_CHANNEL_B: u16 = 0x02;
+pub const FR_NULL_FRAME: u32 = 0x01;
+
+/// Classic or FD frame borrowed from a BLF object
+#[derive(Debug, Clone, Copy)]
+pub struct Frame<'a> {
+    pub ts: i64,
+    pub channel: u16,
+    pub id: u32,
+    /// 29-bit identifier
+    pub ext: bool,
+    pub data: &'a [u8],
+    /// Data length code as logged, for CAN FD it is the 0-15 code, not the byte count
+    pub dlc: u8,
+    /// Remote transmission request, data is not meaningful
+    pub rtr: bool,
+    pub fd: bool,
+    pub brs: bool,
+    pub esi: bool,
+    /// 0 Rx, 1 Tx, 2 LIN Tx request (header only)
+    pub dir: u8,
+}
+
+/// LIN frame with its checksum, for the LIN sidecar of a CAN conversion
+#[derive(Debug, Clone, Copy)]
+pub struct LinFrame<'a> {
+    pub ts: i64,
+    pub channel: u16,
+    /// 6-bit frame identifier
+    pub id: u8,
+    pub data: &'a [u8],
+    pub checksum: u8,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct FlexRayFrame<'a> {
+    pub ts: i64,
+    /// BLF application channel
+    pub channel: u16,
+    pub slot: u16,
+    pub cycle: u8,
+    /// FlexRay channel A/B bits
+    pub channel_mask: u16,
+    pub payload: &'a [u8],
+    pub null_frame: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct EthernetFrame<'a> {
+    pub ts: i64,
+    pub channel: u16,
+    pub source: [u8; 6],
+    pub destination: [u8; 6],
+    pub ethertype: u16,
+    pub payload: &'a [u8],
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ErrorFrame {
+    pub ts: i64,
+    pub channel: u16,
+    pub error_code: Option<u8>,
+    /// Frame on the bus when the error occurred, as recorded by CAN_ERROR_EXT, 0 for CAN_ERROR
+    pub id: u32,
+    pub ext: bool,
+    /// Classic DLC of that frame, 0 for CAN_ERROR
+    pub dlc: u8,
+    pub data: [u8; 8],
+}
+
+/// Periodic CAN_STATISTIC object of the logger
+#[derive(Debug, Clone, Copy)]
+pub struct BusStatistic {
+    pub ts: i64,
+    pub channel: u16,
+    /// Percent, stored by the logger in 1/100 %
+    pub bus_load: f64,
+    /// Data and remote frames
+    pub std_frames: u32,
+    pub ext_frames: u32,
+    pub error_frames: u32,
+}
+
+/// CANoe write-window text or trigger marker
+#[derive(Debug, Clone)]
+pub struct Event<'a> {
+    pub ts: i64,
+    pub text: Cow<'a, str>,
+}
+
+/// Warns once per process about timestamp flags outside of the BLF specification
+static UNKNOWN_TIME_FLAGS: Once = Once::new();
+
+/// Object timestamp as a duration since the measurement start, `flags` of the object header give its unit:
+/// 10 µs or 1 ns. Other values are read as nanoseconds, as most loggers write them, with a warning.
+pub fn object_time(flags: u32, timestamp: u64) -> Duration {
+    match flags & OBJ_FLAG_TIME_MASK {
+        OBJ_FLAG_TIME_TEN_MICS => Duration::from_micros(timestamp.saturating_mul(10)),
+        OBJ_FLAG_TIME_ONE_NANS => Duration::from_nanos(timestamp),
+        status => {
+            UNKNOWN_TIME_FLAGS.call_once(|| {
+                eprintln!("Warning: unknown object timestamp flags {:#x}, read as nanoseconds", status);
+            });
+            Duration::from_nanos(timestamp)
+        }
+    }
+}
+
+/// Absolute frame time in nanoseconds since Unix epoch
+pub fn frame_timestamp(start_timestamp: DateTime<Utc>, flags: u32, timestamp_ns: u64) -> i64 {
+    let ts = start_timestamp + object_time(flags, timestamp_ns);
+    ts.timestamp() * 1_000_000_000i64 + ts.timestamp_subsec_nanos() as i64
+}
+
+/// Absolute time of the objects decoded by this module, None for the other types
+pub fn object_timestamp(data: &ObjectTypes, start_timestamp: DateTime<Utc>) -> Option<i64> {
+    let header = match data {
+        ObjectTypes::CanMessage1(msg) => &msg.header,
+        ObjectTypes::CanMessage86(msg) => &msg.header,
+        ObjectTypes::CanFdMessage100(msg) => &msg.header,
+        ObjectTypes::CanFdMessage64_101(msg) => &msg.header,
+        ObjectTypes::LinMessage11(msg) => &msg.header,
+        ObjectTypes::FlexRayRcvMessage50(msg) => &msg.header,
+        ObjectTypes::FlexRayRcvMessageEx66(msg) => &msg.header,
+        ObjectTypes::EthernetFrame71(msg) => &msg.header,
+        ObjectTypes::EthernetFrameEx120(msg) => &msg.header,
+        ObjectTypes::CanError2(msg) => &msg.header,
+        ObjectTypes::CanErrorExt73(msg) => &msg.header,
+        ObjectTypes::CanDriverStatistic4(msg) => &msg.header,
+        ObjectTypes::AppText65(msg) => &msg.header,
+        ObjectTypes::AppTrigger5(msg) => &msg.header,
+        _ => return None,
+    };
+    Some(frame_timestamp(start_timestamp, header.flags, header.timestamp_ns))
+}
+
+/// Decodes CAN_MESSAGE, CAN_MESSAGE2 and, when `fd_enabled`, the CAN FD objects
+pub fn decode_frame(data: &ObjectTypes, start_timestamp: DateTime<Utc>, fd_enabled: bool) -> Option<Frame<'_>> {
+    match data {
+        ObjectTypes::CanMessage1(can_msg) => Some(Frame {
+            ts: frame_timestamp(start_timestamp, can_msg.header.flags, can_msg.header.timestamp_ns),
+            channel: can_msg.channel,
+            id: can_msg.id & CAN_ID_MASK,
+            ext: can_msg.id & CAN_MSG_EXT != 0,
+            data: &can_msg.data[..],
+            dlc: can_msg.dlc,
+            rtr: can_msg.flags & CAN_MSG_RTR != 0,
+            fd: false,
+            brs: false,
+            esi: false,
+            dir: can_msg.flags & CAN_MSG_TX,
+        }),
+        ObjectTypes::CanMessage86(can_msg) => Some(Frame {
+            ts: frame_timestamp(start_timestamp, can_msg.header.flags, can_msg.header.timestamp_ns),
+            channel: can_msg.channel,
+            id: can_msg.id & CAN_ID_MASK,
+            ext: can_msg.id & CAN_MSG_EXT != 0,
+            data: &can_msg.data[..],
+            dlc: can_msg.dlc,
+            rtr: can_msg.flags & CAN_MSG_RTR != 0,
+            fd: false,
+            brs: false,
+            esi: false,
+            dir: can_msg.flags & CAN_MSG_TX,
+        }),
+        ObjectTypes::CanFdMessage100(fd_msg) if fd_enabled => {
+            let len = (fd_msg.valid_data_bytes as usize).min(fd_msg.data.len());
+            Some(Frame {
+                ts: frame_timestamp(start_timestamp, fd_msg.header.flags, fd_msg.header.timestamp_ns),
+                channel: fd_msg.channel,
+                id: fd_msg.id & CAN_ID_MASK,
+                ext: fd_msg.id & CAN_MSG_EXT != 0,
+                data: &fd_msg.data[..len],
+                dlc: fd_msg.dlc,
+                rtr: fd_msg.flags & CAN_MSG_RTR != 0,
+                fd: fd_msg.fd_flags & CAN_FD_EDL != 0,
+                brs: fd_msg.fd_flags & CAN_FD_BRS != 0,
+                esi: fd_msg.fd_flags & CAN_FD_ESI != 0,
+                dir: fd_msg.flags & CAN_MSG_TX,
+            })
+        }
+        ObjectTypes::CanFdMessage64_101(fd_msg) if fd_enabled => {
+            let len = (fd_msg.valid_data_bytes as usize).min(fd_msg.data.len());
+            Some(Frame {
+                ts: frame_timestamp(start_timestamp, fd_msg.header.flags, fd_msg.header.timestamp_ns),
+                channel: u16::from(fd_msg.channel),
+                id: fd_msg.id & CAN_ID_MASK,
+                ext: fd_msg.id & CAN_MSG_EXT != 0,
+                data: &fd_msg.data[..len],
+                dlc: fd_msg.dlc,
+                rtr: fd_msg.flags & CAN_FD64_RTR != 0,
+                fd: fd_msg.flags & CAN_FD64_EDL != 0,
+                brs: fd_msg.flags & CAN_FD64_BRS != 0,
+                esi: fd_msg.flags & CAN_FD64_ESI != 0,
+                dir: fd_msg.dir,
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Data bytes announced by a DLC, codes above 8 map to 12-64 bytes for FD frames and to 8 bytes otherwise
+pub fn dlc_len(dlc: u8, fd: bool) -> usize {
+    match dlc {
+        0..=8 => usize::from(dlc),
+        _ if !fd => 8,
+        9..=12 => 12 + (usize::from(dlc) - 9) * 4,
+        13 => 32,
+        14 => 48,
+        _ => 64,
+    }
+}
+
+/// Decodes LIN_MESSAGE objects
+pub fn decode_lin_frame(data: &ObjectTypes, start_timestamp: DateTime<Utc>) -> Option<Frame<'_>> {
+    match data {
+        ObjectTypes::LinMessage11(lin_msg) => {
+            let len = (lin_msg.dlc as usize).min(lin_msg.data.len());
+            Some(Frame {
+                ts: frame_timestamp(start_timestamp, lin_msg.header.flags, lin_msg.header.timestamp_ns),
+                channel: lin_msg.channel,
+                id: u32::from(lin_msg.id),
+                ext: false,
+                data: &lin_msg.data[..len],
+                dlc: lin_msg.dlc,
+                rtr: false,
+                fd: false,
+                brs: false,
+                esi: false,
+                dir: lin_msg.dir,
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Decodes LIN_MESSAGE objects with their checksum
+pub fn decode_lin_message(data: &ObjectTypes, start_timestamp: DateTime<Utc>) -> Option<LinFrame<'_>> {
+    match data {
+        ObjectTypes::LinMessage11(lin_msg) => {
+            let len = (lin_msg.dlc as usize).min(lin_msg.data.len());
+            Some(LinFrame {
+                ts: frame_timestamp(start_timestamp, lin_msg.header.flags, lin_msg.header.timestamp_ns),
+                channel: lin_msg.channel,
+                id: lin_msg.id & 0x3F,
+                data: &lin_msg.data[..len],
+                // the logger stores the 8-bit checksum in a 16-bit field
+                checksum: lin_msg.crc as u8,
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Decodes FLEXRAY_RCVMESSAGE and FLEXRAY_RCVMESSAGE_EX objects
+pub fn decode_flexray_frame(data: &ObjectTypes, start_timestamp: DateTime<Utc>) -> Option<FlexRayFrame<'_>> {
+    match data {
+        ObjectTypes::FlexRayRcvMessage50(fr_msg) => {
+            let len = (fr_msg.data_count as usize).min(fr_msg.data_bytes.len());
+            Some(FlexRayFrame {
+                ts: frame_timestamp(start_timestamp, fr_msg.header.flags, fr_msg.header.timestamp_ns),
+                channel: fr_msg.channel,
+                slot: fr_msg.frame_id,
+                // cycles count 0 to 63 in a 16-bit field
+                cycle: fr_msg.cycle as u8,
+                channel_mask: fr_msg.channel_mask,
+                payload: &fr_msg.data_bytes[..len],
+                null_frame: fr_msg.frame_flags & FR_NULL_FRAME != 0,
+            })
+        }
+        ObjectTypes::FlexRayRcvMessageEx66(fr_msg) => {
+            let len = (fr_msg.data_count as usize).min(fr_msg.data_bytes.len());
+            Some(FlexRayFrame {
+                ts: frame_timestamp(start_timestamp, fr_msg.header.flags, fr_msg.header.timestamp_ns),
+                channel: fr_msg.channel,
+                slot: fr_msg.frame_id,
+                cycle: fr_msg.cycle as u8,
+                channel_mask: fr_msg.channel_mask,
+                payload: &fr_msg.data_bytes[..len],
+                null_frame: fr_msg.frame_flags & FR_NULL_FRAME != 0,
+            })
+        }
+        _ => None,
+    }
+}
+
+/// "A", "B" or "AB", None when no channel bit is set
+pub fn flexray_channel_name(channel_mask: u16) -> Option<&'static str> {
+    match channel_mask & (FR_CHANNEL_A | FR_CHANNEL_B) {
+        FR_CHANNEL_A => Some("A"),
+        FR_CHANNEL_B => Some("B"),
+        0 => None,
+        _ => Some("AB"),
+    }
+}
+
+const ETHERTYPE_VLAN: u16 = 0x8100;
+
+/// Decodes ETHERNET_FRAME and ETHERNET_FRAME_EX objects, VLAN tags are skipped
+pub fn decode_ethernet_frame(data: &ObjectTypes, start_timestamp: DateTime<Utc>) -> Option<EthernetFrame<'_>> {
+    match data {
+        ObjectTypes::EthernetFrame71(eth_msg) => Some(EthernetFrame {
+            ts: frame_timestamp(start_timestamp, eth_msg.header.flags, eth_msg.header.timestamp_ns),
+            channel: eth_msg.channel,
+            source: eth_msg.source_address,
+            destination: eth_msg.destination_address,
+            ethertype: eth_msg.ether_type,
+            payload: &eth_msg.payload[..],
+        }),
+        ObjectTypes::EthernetFrameEx120(eth_msg) => {
+            // frame_data holds the raw frame starting from the destination MAC
+            let raw = &eth_msg.frame_data[..];
+            if raw.len() < 14 {
+                return None;
+            }
+            let mut offset = 12;
+            let mut ethertype = u16::from_be_bytes([raw[offset], raw[offset + 1]]);
+            while ethertype == ETHERTYPE_VLAN && raw.len() >= offset + 6 {
+                offset += 4;
+                ethertype = u16::from_be_bytes([raw[offset], raw[offset + 1]]);
+            }
+            Some(EthernetFrame {
+                ts: frame_timestamp(start_timestamp, eth_msg.header.flags, eth_msg.header.timestamp_ns),
+                channel: eth_msg.channel,
+                source: raw[6..12].try_into().ok()?,
+                destination: raw[0..6].try_into().ok()?,
+                ethertype,
+                payload: &raw[offset + 2..],
+            })
+        }
+        _ => None,
+    }
+}
+
+pub fn format_mac(mac: &[u8; 6]) -> String {
+    mac.iter().map(|byte| format!("{:02x}", byte)).collect::<Vec<_>>().join(":")
+}
+
+/// Decodes CAN_ERROR and CAN_ERROR_EXT objects
+pub fn decode_error_frame(data: &ObjectTypes, start_timestamp: DateTime<Utc>) -> Option<ErrorFrame> {
+    match data {
+        ObjectTypes::CanError2(err_msg) => Some(ErrorFrame {
+            ts: frame_timestamp(start_timestamp, err_msg.header.flags, err_msg.header.timestamp_ns),
+            channel: err_msg.channel,
+            error_code: None,
+            id: 0,
+            ext: false,
+            dlc: 0,
+            data: [0; 8],
+        }),
+        ObjectTypes::CanErrorExt73(err_msg) => Some(ErrorFrame {
+            ts: frame_timestamp(start_timestamp, err_msg.header.flags, err_msg.header.timestamp_ns),
+            channel: err_msg.channel,
+            error_code: Some(err_msg.ecc),
+            id: err_msg.id & CAN_ID_MASK,
+            ext: err_msg.id & CAN_MSG_EXT != 0,
+            // the upper 4 bits are reserved
+            dlc: err_msg.dlc & 0x0F,
+            data: err_msg.data,
+        }),
+        _ => None,
+    }
+}
+
+/// Decodes CAN_STATISTIC objects
+pub fn decode_bus_statistic(data: &ObjectTypes, start_timestamp: DateTime<Utc>) -> Option<BusStatistic> {
+    match data {
+        ObjectTypes::CanDriverStatistic4(stat) => Some(BusStatistic {
+            ts: frame_timestamp(start_timestamp, stat.header.flags, stat.header.timestamp_ns),
+            channel: stat.channel,
+            bus_load: f64::from(stat.bus_load) / 100.0,
+            std_frames: stat.standard_data_frames.saturating_add(stat.standard_remote_frames),
+            ext_frames: stat.extended_data_frames.saturating_add(stat.extended_remote_frames),
+            error_frames: stat.error_frames,
+        }),
+        _ => None,
+    }
+}
+
+/// Decodes APP_TEXT and APP_TRIGGER objects, empty texts are skipped
+pub fn decode_event(data: &ObjectTypes, start_timestamp: DateTime<Utc>) -> Option<Event<'_>> {
+    let event = match data {
+        ObjectTypes::AppText65(app_text) => Event {
+            ts: frame_timestamp(start_timestamp, app_text.header.flags, app_text.header.timestamp_ns),
+            // lossy for the non UTF-8 texts, without the terminating NUL
+            text: app_text.to_string(),
+        },
+        // triggers carry no text, the object name marks them
+        ObjectTypes::AppTrigger5(app_trigger) => Event {
+            ts: frame_timestamp(start_timestamp, app_trigger.header.flags, app_trigger.header.timestamp_ns),
+            text: Cow::Borrowed("AppTrigger"),
+        },
+        _ => return None,
+    };
+    if event.text.trim().is_empty() {
+        return None;
+    }
+    Some(event)
+}
+
+/// Variant name of a BLF object, e.g. "CanMessage86"
+pub fn object_type_name(data: &ObjectTypes) -> String {
+    let debug = format!("{:?}", data);
+    debug.split(|c: char| !(c.is_alphanumeric() || c == '_'))
+         .next()
+         .unwrap_or_default()
+         .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use ablf::ObjectHeader;
+    use chrono::TimeZone;
+    use super::*;
+
+    fn object_header(flags: u32, timestamp_ns: u64) -> ObjectHeader {
+        ObjectHeader { flags, client_index: 0, version: 0, timestamp_ns }
+    }
+
+    #[test]
+    fn ten_microsecond_timestamps() {
+        let header = object_header(OBJ_FLAG_TIME_TEN_MICS, 12_345);
+        assert_eq!(object_time(header.flags, header.timestamp_ns), Duration::from_micros(123_450));
+    }
+
+    #[test]
+    fn nanosecond_timestamps() {
+        let header = object_header(OBJ_FLAG_TIME_ONE_NANS, 123_456_789);
+        assert_eq!(object_time(header.flags, header.timestamp_ns), Duration::from_nanos(123_456_789));
+    }
+
+    #[test]
+    fn only_the_time_bits_give_the_unit() {
+        let header = object_header(0x40 | OBJ_FLAG_TIME_TEN_MICS, 1);
+        assert_eq!(object_time(header.flags, header.timestamp_ns), Duration::from_micros(10));
+        // no or both bits, read as nanoseconds
+        for flags in [0, OBJ_FLAG_TIME_MASK] {
+            assert_eq!(object_time(flags, 1), Duration::from_nanos(1));
+        }
+    }
+
+    #[test]
+    fn frame_timestamp_adds_the_measurement_start() {
+        let start = Utc.with_ymd_and_hms(2024, 6, 15, 10, 0, 0).unwrap();
+        let start_ns = start.timestamp_nanos_opt().unwrap();
+        let header = object_header(OBJ_FLAG_TIME_TEN_MICS, 100);
+        assert_eq!(frame_timestamp(start, header.flags, header.timestamp_ns), start_ns + 1_000_000);
+        let header = object_header(OBJ_FLAG_TIME_ONE_NANS, 100);
+        assert_eq!(frame_timestamp(start, header.flags, header.timestamp_ns), start_ns + 100);
+    }
+}
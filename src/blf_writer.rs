@@ -0,0 +1,200 @@
+//! Minimal BLF writer: CAN_MESSAGE2 objects in uncompressed log containers
+
+use std::io::{self, Seek, SeekFrom, Write};
+use chrono::{DateTime, Datelike, Timelike, Utc};
+
+const FILE_HEADER_SIZE: usize = 144;
+const OBJ_HEADER_BASE_SIZE: usize = 16;
+const OBJ_HEADER_V1_SIZE: usize = 16;
+const LOG_CONTAINER_SIZE: usize = 16;
+const CAN_MESSAGE2_SIZE: usize = 24;
+
+const OBJ_TYPE_CAN_MESSAGE2: u32 = 86;
+const OBJ_TYPE_LOG_CONTAINER: u32 = 10;
+pub use crate::blf::CAN_MSG_EXT;
+use crate::blf::OBJ_FLAG_TIME_ONE_NANS;
+
+/// Uncompressed bytes collected before a container is written
+const CONTAINER_SIZE: usize = 128 * 1024;
+
+fn system_time(ts: DateTime<Utc>) -> [u8; 16] {
+    let fields = [
+        ts.year() as u16,
+        ts.month() as u16,
+        ts.weekday().num_days_from_sunday() as u16,
+        ts.day() as u16,
+        ts.hour() as u16,
+        ts.minute() as u16,
+        ts.second() as u16,
+        (ts.timestamp_subsec_millis().min(999)) as u16,
+    ];
+    let mut bytes = [0u8; 16];
+    for (chunk, field) in bytes.chunks_exact_mut(2).zip(fields) {
+        chunk.copy_from_slice(&field.to_le_bytes());
+    }
+    bytes
+}
+
+fn object_header(object_type: u32, header_size: usize, object_size: usize) -> Vec<u8> {
+    let mut header = Vec::with_capacity(OBJ_HEADER_BASE_SIZE);
+    header.extend_from_slice(b"LOBJ");
+    header.extend_from_slice(&(header_size as u16).to_le_bytes());
+    header.extend_from_slice(&1u16.to_le_bytes());
+    header.extend_from_slice(&(object_size as u32).to_le_bytes());
+    header.extend_from_slice(&object_type.to_le_bytes());
+    header
+}
+
+pub struct BlfWriter<W: Write + Seek> {
+    inner: W,
+    /// Measurement start, nanoseconds since Unix epoch
+    start: i64,
+    last: i64,
+    container: Vec<u8>,
+    objects: u32,
+    uncompressed: u64,
+}
+
+impl<W: Write + Seek> BlfWriter<W> {
+    /// `start` is the measurement start in nanoseconds since Unix epoch, frames must not precede it
+    pub fn new(mut inner: W, start: i64) -> io::Result<Self> {
+        // the header start time has millisecond resolution
+        let start = start - start.rem_euclid(1_000_000);
+        // placeholder, rewritten by finish once sizes and counts are known
+        inner.write_all(&[0u8; FILE_HEADER_SIZE])?;
+        Ok(BlfWriter {
+            inner,
+            start,
+            last: start,
+            container: Vec::with_capacity(CONTAINER_SIZE),
+            objects: 0,
+            uncompressed: FILE_HEADER_SIZE as u64,
+        })
+    }
+
+    /// Appends a classic CAN frame, `data` longer than 8 bytes is rejected
+    pub fn write_can_message(&mut self, ts: i64, channel: u16, id: u32, flags: u8, data: &[u8]) -> io::Result<()> {
+        if data.len() > 8 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "CAN_MESSAGE2 holds up to 8 data bytes"));
+        }
+        // channel, flags, dlc, id, data[8], frame length, bit count, reserved
+        let mut body = Vec::with_capacity(CAN_MESSAGE2_SIZE);
+        body.extend_from_slice(&channel.to_le_bytes());
+        body.push(flags);
+        body.push(data.len() as u8);
+        body.extend_from_slice(&id.to_le_bytes());
+        let mut payload = [0u8; 8];
+        payload[..data.len()].copy_from_slice(data);
+        body.extend_from_slice(&payload);
+        body.extend_from_slice(&[0u8; 8]);
+        self.write_object(OBJ_TYPE_CAN_MESSAGE2, ts, &body)
+    }
+
+    /// Appends an object of `object_type` whose `body` follows the v1 object header, unpadded
+    pub(crate) fn write_object(&mut self, object_type: u32, ts: i64, body: &[u8]) -> io::Result<()> {
+        let size = OBJ_HEADER_BASE_SIZE + OBJ_HEADER_V1_SIZE + body.len();
+        let mut object = object_header(object_type, OBJ_HEADER_BASE_SIZE + OBJ_HEADER_V1_SIZE, size);
+        // header v1: flags, client index, object version, timestamp
+        object.extend_from_slice(&OBJ_FLAG_TIME_ONE_NANS.to_le_bytes());
+        object.extend_from_slice(&0u16.to_le_bytes());
+        object.extend_from_slice(&0u16.to_le_bytes());
+        object.extend_from_slice(&(ts.saturating_sub(self.start).max(0) as u64).to_le_bytes());
+        object.extend_from_slice(body);
+
+        self.container.extend_from_slice(&object);
+        self.objects += 1;
+        self.last = self.last.max(ts);
+        if self.container.len() >= CONTAINER_SIZE {
+            self.flush_container()?;
+        }
+        Ok(())
+    }
+
+    fn flush_container(&mut self) -> io::Result<()> {
+        if self.container.is_empty() {
+            return Ok(());
+        }
+        let size = OBJ_HEADER_BASE_SIZE + LOG_CONTAINER_SIZE + self.container.len();
+        let mut header = object_header(OBJ_TYPE_LOG_CONTAINER, OBJ_HEADER_BASE_SIZE, size);
+        // compression method 0 (none), reserved, uncompressed size, reserved
+        header.extend_from_slice(&0u16.to_le_bytes());
+        header.extend_from_slice(&[0u8; 6]);
+        header.extend_from_slice(&(self.container.len() as u32).to_le_bytes());
+        header.extend_from_slice(&[0u8; 4]);
+
+        self.inner.write_all(&header)?;
+        self.inner.write_all(&self.container)?;
+        self.inner.write_all(&vec![0u8; size % 4])?;
+        self.uncompressed += (size + size % 4) as u64;
+        self.container.clear();
+        Ok(())
+    }
+
+    /// Writes the last container and the file header, returns the number of objects
+    pub fn finish(mut self) -> io::Result<u32> {
+        self.flush_container()?;
+        let file_size = self.inner.stream_position()?;
+
+        let mut header = Vec::with_capacity(FILE_HEADER_SIZE);
+        header.extend_from_slice(b"LOGG");
+        header.extend_from_slice(&(FILE_HEADER_SIZE as u32).to_le_bytes());
+        // application id and version, api version
+        header.extend_from_slice(&[5, 0, 0, 0, 4, 7, 1, 0]);
+        header.extend_from_slice(&file_size.to_le_bytes());
+        header.extend_from_slice(&self.uncompressed.to_le_bytes());
+        header.extend_from_slice(&self.objects.to_le_bytes());
+        header.extend_from_slice(&self.objects.to_le_bytes());
+        header.extend_from_slice(&system_time(DateTime::from_timestamp_nanos(self.start)));
+        header.extend_from_slice(&system_time(DateTime::from_timestamp_nanos(self.last)));
+        header.resize(FILE_HEADER_SIZE, 0);
+
+        self.inner.seek(SeekFrom::Start(0))?;
+        self.inner.write_all(&header)?;
+        self.inner.flush()?;
+        Ok(self.objects)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use ablf::BlfFile;
+    use chrono::TimeZone;
+    use super::*;
+    use crate::blf::{CAN_MSG_RTR, CAN_MSG_TX, decode_frame};
+
+    #[test]
+    fn frames_read_back_with_ablf() {
+        // the nanoseconds do not survive a round trip through f64 seconds
+        let start = 1_718_445_005_123_456_789;
+        let frames: [(i64, u16, u32, u8, &[u8]); 4] = [
+            (start, 1, 0x123, 0, &[1, 2, 3]),
+            (start + 1, 2, 0x18FEF100 | CAN_MSG_EXT, CAN_MSG_TX, &[0xFF; 8]),
+            (start + 999_999_999, 1, 0x7FF, CAN_MSG_RTR, &[]),
+            (start + 3_600_000_000_007, 3, 0x001, 0, &[0]),
+        ];
+        let mut blf = Cursor::new(Vec::new());
+        let mut writer = BlfWriter::new(&mut blf, start).unwrap();
+        for (ts, channel, id, flags, data) in frames {
+            writer.write_can_message(ts, channel, id, flags, data).unwrap();
+        }
+        assert_eq!(writer.finish().unwrap(), 4);
+
+        blf.set_position(0);
+        let blf = BlfFile::from_reader(blf).map_err(|(error, _)| error).unwrap();
+        let start_timestamp = Utc.from_utc_datetime(&blf.file_stats.measurement_start_time().unwrap());
+        let objects: Vec<_> = blf.into_iter().collect();
+        assert_eq!(objects.len(), frames.len());
+        for (object, (ts, channel, id, flags, data)) in objects.iter().zip(frames) {
+            let frame = decode_frame(&object.data, start_timestamp, false).unwrap();
+            assert_eq!(frame.ts, ts);
+            assert_eq!(frame.channel, channel);
+            assert_eq!(frame.id, id & !CAN_MSG_EXT);
+            assert_eq!(frame.ext, id & CAN_MSG_EXT != 0);
+            assert_eq!(frame.dlc as usize, data.len());
+            assert_eq!(&frame.data[..data.len()], data);
+            assert_eq!(frame.rtr, flags & CAN_MSG_RTR != 0);
+            assert_eq!(frame.dir, flags & CAN_MSG_TX);
+        }
+    }
+}
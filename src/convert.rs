@@ -0,0 +1,1552 @@
+//! BLF to parquet conversion
+
+use std::{
+    borrow::Cow,
+    cmp::Reverse,
+    collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap, VecDeque, btree_map::Entry},
+    io::{self, BufRead, BufWriter, Seek, Write},
+    mem,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{Receiver, SyncSender, sync_channel},
+    },
+    thread::{self, ScopedJoinHandle},
+    time::{Duration, Instant},
+};
+use ablf::{BlfFile, Object, ObjectTypes};
+use chrono::{DateTime, TimeZone, Utc};
+use clap::ValueEnum;
+use arrow::{
+    array::{AsArray, BooleanArray},
+    compute::filter_record_batch,
+    datatypes::{SchemaRef, UInt16Type},
+    ipc::writer::FileWriter,
+    record_batch::RecordBatch,
+};
+use parquet::{
+    arrow::ArrowWriter,
+    file::{metadata::KeyValue, properties::WriterProperties},
+};
+use crate::{
+    blf::{
+        Frame, decode_bus_statistic, decode_error_frame, decode_ethernet_frame, decode_event, decode_flexray_frame,
+        decode_frame, decode_lin_frame, decode_lin_message, dlc_len, object_timestamp, object_type_name,
+    },
+    dbc::Dbc,
+    error::Error,
+    ids::{id_allowed, id_masked},
+    j1939::J1939Id,
+    parquet_writer::{
+        BusStatistics, Codec, ErrorFrames, EthernetFrames, Events, ExtraColumns, FlexRayFrames, FrameBuffer,
+        Frames, LinFrames, TsType, ethernet_schema, flexray_schema, frame_schema, with_signal_fields,
+        writer_properties,
+    },
+    progress::progress_bar,
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Protocol {
+    #[default]
+    Can,
+    Lin,
+    /// Written with the slot, cycle, channel and payload schema
+    #[value(name = "flexray")]
+    FlexRay,
+    /// Written with the channel, MAC addresses, ethertype and payload schema
+    Ethernet,
+}
+
+/// Format of the frame output, the sidecar files are always parquet
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    #[default]
+    Parquet,
+    /// ts,id,data_hex lines, CAN and LIN only
+    Csv,
+    /// Arrow IPC file (Feather v2), same schema as the parquet output
+    ArrowIpc,
+}
+
+/// Check of the frame timestamps going backwards in BLF order, before each written batch is sorted by ts
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Monotonic {
+    /// Timestamps are not compared
+    #[default]
+    Off,
+    /// Each backwards jump is printed to stderr and counted
+    Warn,
+    /// The first backwards jump stops the conversion with an error
+    Error,
+}
+
+/// Handling of the CAN or LIN frames whose data length differs from the one announced by their DLC
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum DlcMismatch {
+    /// Written with the data as logged
+    #[default]
+    Keep,
+    /// Dropped
+    Skip,
+    /// Data cut or zero-padded to the DLC length
+    Truncate,
+}
+
+/// What the start and end percentages count
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum PercentOf {
+    /// BLF objects of every type, in merged order
+    #[default]
+    Objects,
+    /// CAN or LIN frames kept by the channel, ID, payload and time filters
+    Matched,
+}
+
+#[derive(Debug, Clone)]
+pub struct ConvertOptions {
+    /// BLF channels, 1-based, empty converts every channel
+    pub channels: Vec<u16>,
+    /// CAN or LIN IDs to convert, without the extended bit, empty converts every ID
+    pub include_id: Vec<u32>,
+    /// CAN or LIN IDs dropped, after `include_id` and `id_masks`
+    pub exclude_id: Vec<u32>,
+    /// `(mask, value)` pairs keeping an ID when `id & mask == value`, OR'ed with each other and `include_id`
+    pub id_masks: Vec<(u32, u32)>,
+    /// J1939 PGNs of the extended CAN frames to convert, AND'ed with the ID filters, empty converts every frame
+    pub pgns: Vec<u32>,
+    /// `(offset, value)` pairs a CAN or LIN payload must all hold, a frame too short for an offset is dropped
+    pub data_match: Vec<(usize, u8)>,
+    /// Fewest CAN or LIN data bytes a frame must carry
+    pub min_dlc: Option<usize>,
+    /// Most CAN or LIN data bytes a frame may carry, up to 64 with FD frames
+    pub max_dlc: Option<usize>,
+    /// Frames whose data length does not match their DLC, counted in `ConvertSummary::dlc_mismatch`
+    pub dlc_mismatch: DlcMismatch,
+    pub protocol: Protocol,
+    pub format: Format,
+    pub start_percentage: f64,
+    pub end_percentage: f64,
+    pub percent_of: PercentOf,
+    /// Frames matched over the whole input with `PercentOf::Matched`, as returned by `count_matched`
+    pub matched_frames: Option<usize>,
+    /// Absolute window in nanoseconds since Unix epoch, frames must also be in the percentage window
+    pub start_time: Option<i64>,
+    pub end_time: Option<i64>,
+    /// Window in seconds from the measurement start of the earliest file, intersected with the absolute one
+    pub start_seconds: Option<f64>,
+    pub end_seconds: Option<f64>,
+    /// Shortest time between two kept frames of the same ID, CAN and LIN only, every frame kept when None
+    pub min_interval: Option<Duration>,
+    /// Drop the frames whose data did not change since the last kept frame of their ID, CAN and LIN only
+    pub on_change: bool,
+    /// With `on_change`, keep an unchanged frame when its ID was last kept at least this long before
+    pub on_change_heartbeat: Option<Duration>,
+    /// Frames written before the conversion stops, after every other filter, unlimited when None
+    pub max_frames: Option<usize>,
+    /// Convert CAN FD frames too, only used for CAN
+    pub fd: bool,
+    /// Collect CAN error frames of every channel
+    pub errors: bool,
+    /// Write the CAN error frames of the kept channels to the frame output, flagged by an is_error column
+    pub error_rows: bool,
+    /// Collect AppText and AppTrigger objects
+    pub events: bool,
+    /// Collect CAN bus statistics of every channel
+    pub statistics: bool,
+    /// Collect LIN frames of every channel next to the converted protocol
+    pub lin: bool,
+    /// Type of the ts column of every output, Int64 nanoseconds by default
+    pub ts_type: TsType,
+    /// Rows buffered before a record batch is flushed as its own row group
+    pub batch_size: usize,
+    pub compression: Codec,
+    /// Level of `compression`, None uses the codec default
+    pub compression_level: Option<u32>,
+    /// Dictionary encoding, on by default and for the id, channel and dlc columns in particular
+    pub dictionary: bool,
+    /// Stop at the first unreadable object and keep what was converted
+    pub best_effort: bool,
+    /// Unreadable objects skipped before giving up, None gives up at the first one
+    pub max_errors: Option<usize>,
+    /// Adds a column per signal, only used for CAN
+    pub dbc: Option<Dbc>,
+    /// Draw a progress bar over the BLF objects
+    pub progress: bool,
+    /// Print the conversion messages to stderr, for an output written to stdout
+    pub messages_to_stderr: bool,
+    /// Threads decoding the objects next to the reader and the writer, None uses the free cores
+    pub decode_threads: Option<usize>,
+    /// Backwards timestamps of the written frames, only compared when not `Monotonic::Off`
+    pub monotonic: Monotonic,
+}
+
+impl Default for ConvertOptions {
+    fn default() -> Self {
+        ConvertOptions {
+            channels: Vec::new(),
+            include_id: Vec::new(),
+            exclude_id: Vec::new(),
+            id_masks: Vec::new(),
+            pgns: Vec::new(),
+            data_match: Vec::new(),
+            min_dlc: None,
+            max_dlc: None,
+            dlc_mismatch: DlcMismatch::Keep,
+            protocol: Protocol::Can,
+            format: Format::Parquet,
+            start_percentage: 0.0,
+            end_percentage: 100.0,
+            percent_of: PercentOf::Objects,
+            matched_frames: None,
+            start_time: None,
+            end_time: None,
+            start_seconds: None,
+            end_seconds: None,
+            min_interval: None,
+            on_change: false,
+            on_change_heartbeat: None,
+            max_frames: None,
+            fd: false,
+            errors: false,
+            error_rows: false,
+            events: false,
+            statistics: false,
+            lin: false,
+            ts_type: TsType::Int64,
+            batch_size: 100_000,
+            compression: Codec::Snappy,
+            compression_level: None,
+            dictionary: true,
+            best_effort: false,
+            max_errors: None,
+            dbc: None,
+            progress: false,
+            messages_to_stderr: false,
+            decode_threads: None,
+            monotonic: Monotonic::Off,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ConvertSummary {
+    /// Objects declared in the BLF header
+    pub objects: u32,
+    /// Rows written to the parquet output
+    pub rows: usize,
+    /// Frames of the selected protocol on other channels
+    pub other_channel: usize,
+    /// CAN or LIN frames on a kept channel dropped by `ConvertOptions::include_id`, `id_masks`, `exclude_id` or `pgns`
+    pub other_id: usize,
+    /// Frames kept by `ConvertOptions::id_masks` only, not in `include_id`
+    pub masked_id: usize,
+    /// CAN or LIN frames with a kept ID dropped by `ConvertOptions::data_match`
+    pub other_data: usize,
+    /// CAN or LIN frames with a kept ID dropped by `ConvertOptions::min_dlc` or `max_dlc`
+    pub other_dlc: usize,
+    /// CAN or LIN frames with a kept ID whose data length does not match their DLC, remote frames excluded
+    pub dlc_mismatch: usize,
+    /// Frames passing every other filter dropped by `ConvertOptions::min_interval`
+    pub downsampled: usize,
+    /// Frames passing every other filter dropped by `ConvertOptions::on_change`
+    pub unchanged: usize,
+    /// Frames kept on each channel, numbered as the channel column
+    pub channels: BTreeMap<u16, usize>,
+    /// IDs of the kept CAN or LIN frames
+    pub ids: BTreeSet<u32>,
+    /// Objects outside the percentage or time window, including the ones not read after it
+    pub out_of_range: usize,
+    /// Objects of other protocols or types, error frames excluded when collected
+    pub other_type: usize,
+    pub fd_frames: usize,
+    /// Error frames written to the frame output with `ConvertOptions::error_rows`
+    pub error_rows: usize,
+    /// Frames written with a timestamp lower than the previous frame, counted with `Monotonic::Warn`
+    pub non_monotonic: usize,
+    /// FlexRay null frames and frames without channel A/B
+    pub flexray_skipped: usize,
+    /// Collected only when `ConvertOptions::errors` is set
+    pub errors: ErrorFrames,
+    /// Collected only when `ConvertOptions::events` is set
+    pub events: Events,
+    /// Collected only when `ConvertOptions::statistics` is set
+    pub statistics: BusStatistics,
+    /// Collected only when `ConvertOptions::lin` is set
+    pub lin: LinFrames,
+    /// `ConvertOptions::max_frames` was reached, the channel and file counts may include frames decoded past it
+    pub limit_reached: bool,
+    /// Objects merged when the first truncated file ended, only set with `ConvertOptions::best_effort`
+    pub truncated: Option<u32>,
+    /// Unreadable objects skipped, up to `ConvertOptions::max_errors`
+    pub corrupt: usize,
+    /// Object types seen before the first matching frame
+    pub unsupported: BTreeMap<String, usize>,
+    /// Merged files, in measurement start order
+    pub files: Vec<FileSummary>,
+    /// Time the decoders spent on the objects, summed over the threads
+    pub decode_time: Duration,
+    /// Time the calling thread spent collecting and writing the rows
+    pub write_time: Duration,
+    /// Wall time of the pipeline, from the first object read to the output closed
+    pub pipeline_time: Duration,
+}
+
+#[derive(Debug, Default)]
+pub struct FileSummary {
+    /// Position of the file in the readers passed to `blfs_to_parquet`
+    pub input: usize,
+    /// Objects declared in the BLF header
+    pub objects: u32,
+    /// Frames kept by the filters, before `ConvertOptions::on_change` and `min_interval`
+    pub frames: usize,
+    pub first_ts: Option<i64>,
+    pub last_ts: Option<i64>,
+}
+
+impl ConvertSummary {
+    /// Decoding and writing time over the wall time: what running the stages one after the other would cost
+    pub fn speedup(&self) -> f64 {
+        (self.decode_time + self.write_time).as_secs_f64() / self.pipeline_time.as_secs_f64().max(1e-9)
+    }
+
+    /// Counts a frame kept on the 1-based BLF `channel`
+    fn add_channel(&mut self, channel: u16) {
+        *self.channels.entry(channel.saturating_sub(1)).or_insert(0) += 1;
+    }
+
+    /// Adds the counts and sidecar rows of a chunk following the ones already merged
+    fn merge(&mut self, mut chunk: ConvertSummary) {
+        self.other_channel += chunk.other_channel;
+        self.other_id += chunk.other_id;
+        self.masked_id += chunk.masked_id;
+        self.other_data += chunk.other_data;
+        self.other_dlc += chunk.other_dlc;
+        self.dlc_mismatch += chunk.dlc_mismatch;
+        self.out_of_range += chunk.out_of_range;
+        self.other_type += chunk.other_type;
+        self.fd_frames += chunk.fd_frames;
+        self.error_rows += chunk.error_rows;
+        self.non_monotonic += chunk.non_monotonic;
+        self.flexray_skipped += chunk.flexray_skipped;
+        self.corrupt += chunk.corrupt;
+        self.decode_time += chunk.decode_time;
+        for (channel, frames) in chunk.channels {
+            *self.channels.entry(channel).or_insert(0) += frames;
+        }
+        self.ids.append(&mut chunk.ids);
+        self.errors.append(&mut chunk.errors);
+        self.events.append(&mut chunk.events);
+        self.statistics.append(&mut chunk.statistics);
+        self.lin.append(&mut chunk.lin);
+    }
+}
+
+impl FileSummary {
+    fn add(&mut self, ts: i64) {
+        self.frames += 1;
+        self.first_ts = Some(self.first_ts.map_or(ts, |first| first.min(ts)));
+        self.last_ts = Some(self.last_ts.map_or(ts, |last| last.max(ts)));
+    }
+
+    fn merge(&mut self, chunk: &FileSummary) {
+        self.frames += chunk.frames;
+        if let Some(first) = chunk.first_ts {
+            self.first_ts = Some(self.first_ts.map_or(first, |ts| ts.min(first)));
+        }
+        if let Some(last) = chunk.last_ts {
+            self.last_ts = Some(self.last_ts.map_or(last, |ts| ts.max(last)));
+        }
+    }
+}
+
+/// Frames logged out of order are still read this long past the end of the time window
+const TIME_WINDOW_SLACK: i64 = 1_000_000_000;
+
+enum TimeWindow {
+    /// Outside the window, the next objects may be inside
+    Before,
+    Inside,
+    /// Past the window and its slack, no object is read after it
+    After,
+}
+
+fn channel_match(channel: u16, opts: &ConvertOptions) -> bool {
+    opts.channels.is_empty() || opts.channels.contains(&channel)
+}
+
+/// None when the ID is dropped, Some(true) when only a mask keeps it
+fn id_match(id: u32, opts: &ConvertOptions) -> Option<bool> {
+    if opts.id_masks.is_empty() {
+        return id_allowed(id, &opts.include_id, &opts.exclude_id).then_some(false);
+    }
+    if opts.exclude_id.contains(&id) {
+        None
+    } else if opts.include_id.contains(&id) {
+        Some(false)
+    } else {
+        id_masked(id, &opts.id_masks).then_some(true)
+    }
+}
+
+/// The frame is extended and its J1939 PGN is in `pgns`, or `pgns` is empty
+fn pgn_match(frame: &Frame, opts: &ConvertOptions) -> bool {
+    opts.pgns.is_empty() || frame.ext && opts.pgns.contains(&J1939Id::from_id(frame.id).pgn)
+}
+
+/// The data length is within `min_dlc` and `max_dlc`
+fn dlc_match(data: &[u8], opts: &ConvertOptions) -> bool {
+    opts.min_dlc.is_none_or(|min| data.len() >= min) && opts.max_dlc.is_none_or(|max| data.len() <= max)
+}
+
+/// Every `(offset, value)` pair holds in `data`
+fn data_match(data: &[u8], opts: &ConvertOptions) -> bool {
+    opts.data_match.iter().all(|&(offset, value)| data.get(offset) == Some(&value))
+}
+
+fn time_window(ts: i64, opts: &ConvertOptions) -> TimeWindow {
+    if opts.start_time.is_some_and(|start| ts < start) {
+        TimeWindow::Before
+    } else if opts.end_time.is_some_and(|end| ts > end.saturating_add(TIME_WINDOW_SLACK)) {
+        TimeWindow::After
+    } else if opts.end_time.is_some_and(|end| ts > end) {
+        TimeWindow::Before
+    } else {
+        TimeWindow::Inside
+    }
+}
+
+/// Opens the output of a channel, numbered as the channel column
+type OpenChannel<'a, W> = Box<dyn FnMut(u16) -> Result<W, Error> + 'a>;
+
+/// Where the converted rows go, a single writer, a writer per channel or nowhere
+enum Target<'a, W> {
+    Writer(W),
+    Channels(OpenChannel<'a, W>),
+    Discard,
+}
+
+/// Frame rows written as parquet row groups or streamed as CSV lines
+enum Output<'a, W: Write + Send> {
+    Parquet(ArrowWriter<W>),
+    Csv(BufWriter<W>),
+    ArrowIpc(FileWriter<BufWriter<W>>),
+    /// A parquet per channel, each opened on its first rows
+    Channels {
+        writers: BTreeMap<u16, ArrowWriter<W>>,
+        open: OpenChannel<'a, W>,
+        props: WriterProperties,
+    },
+    /// Rows are only counted
+    Discard,
+}
+
+impl<'a, W: Write + Send> Output<'a, W> {
+    fn new(target: Target<'a, W>, schema: &SchemaRef, opts: &ConvertOptions) -> Result<Self, Error> {
+        // a flush holds up to a decoded batch more than `batch_size`, it must stay one row group
+        let props = writer_properties(opts.compression, opts.compression_level, opts.dictionary,
+                                      opts.batch_size + BATCH_OBJECTS)?;
+        Ok(match (target, opts.format) {
+            (Target::Channels(open), _) => Output::Channels { writers: BTreeMap::new(), open, props },
+            (Target::Discard, _) => Output::Discard,
+            (Target::Writer(writer), Format::Parquet) => {
+                Output::Parquet(ArrowWriter::try_new(writer, schema.clone(), Some(props))?)
+            }
+            (Target::Writer(writer), Format::Csv) => {
+                let mut writer = BufWriter::new(writer);
+                writeln!(writer, "ts,id,data_hex")?;
+                Output::Csv(writer)
+            }
+            (Target::Writer(writer), Format::ArrowIpc) => {
+                Output::ArrowIpc(FileWriter::try_new(BufWriter::new(writer), schema)?)
+            }
+        })
+    }
+
+    /// `metadata` goes to the footer of the parquet and Arrow IPC files, CSV has no place for it
+    fn close(self, metadata: &[KeyValue]) -> Result<(), Error> {
+        match self {
+            // writer must be closed to write footer
+            Output::Parquet(mut writer) => {
+                metadata.iter().for_each(|kv| writer.append_key_value_metadata(kv.clone()));
+                writer.close()?;
+            }
+            Output::Csv(mut writer) => writer.flush()?,
+            Output::ArrowIpc(mut writer) => {
+                metadata.iter().for_each(|kv| writer.write_metadata(&kv.key, kv.value.clone().unwrap_or_default()));
+                writer.finish()?;
+                writer.into_inner()?.flush()?;
+            }
+            Output::Discard => {}
+            Output::Channels { writers, .. } => {
+                for mut writer in writers.into_values() {
+                    metadata.iter().for_each(|kv| writer.append_key_value_metadata(kv.clone()));
+                    writer.close()?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Writes the rows of each channel of `batch` to the writer of the channel, opened on its first rows
+fn write_channels<W: Write + Send>(writers: &mut BTreeMap<u16, ArrowWriter<W>>, open: &mut OpenChannel<W>,
+                                   props: &WriterProperties, batch: &RecordBatch)
+    -> Result<(), Error>
+{
+    let column = batch.column_by_name("channel")
+                      .and_then(|column| column.as_primitive_opt::<UInt16Type>())
+                      .ok_or_else(|| Error::Options("Only CAN and LIN frames can be split by channel".to_string()))?;
+    let mut channels = column.values().to_vec();
+    channels.sort_unstable();
+    channels.dedup();
+
+    for channel in channels {
+        let mask = BooleanArray::from(column.values().iter().map(|value| *value == channel).collect::<Vec<_>>());
+        let rows = filter_record_batch(batch, &mask)?;
+        let writer = match writers.entry(channel) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                entry.insert(ArrowWriter::try_new(open(channel)?, batch.schema(), Some(props.clone()))?)
+            }
+        };
+        writer.write(&rows)?;
+        writer.flush()?;
+    }
+
+    Ok(())
+}
+
+/// Writes the buffered rows as one batch and row group, or as CSV lines, returns the number of rows written
+fn flush<W, B>(output: &mut Output<'_, W>, schema: &SchemaRef, buffer: &mut B)
+    -> Result<usize, Error>
+where
+    W: Write + Send,
+    B: FrameBuffer,
+{
+    if buffer.is_empty() {
+        return Ok(0);
+    }
+    let rows = buffer.len();
+    match output {
+        Output::Parquet(writer) => {
+            let batch = buffer.build(schema)?;
+            writer.write(&batch)?;
+            // close the row group so the writer does not hold more than one batch
+            writer.flush()?;
+        }
+        Output::Csv(writer) => buffer.write_csv(schema, writer)?,
+        Output::ArrowIpc(writer) => writer.write(&buffer.build(schema)?)?,
+        Output::Channels { writers, open, props } => write_channels(writers, open, props, &buffer.build(schema)?)?,
+        Output::Discard => {}
+    }
+    buffer.clear();
+
+    Ok(rows)
+}
+
+/// Objects read from a BLF before they are handed to a decoder
+const BATCH_OBJECTS: usize = 4096;
+
+/// Object of the merged inputs
+struct MergedObject {
+    /// Position of the file in measurement start order
+    file: usize,
+    /// None for the unreadable objects skipped with `ConvertOptions::max_errors`
+    object: Option<Object>,
+}
+
+/// Consecutive objects of the merged inputs, in timestamp order
+struct ObjectBatch {
+    /// Position of the batch in the merged stream
+    seq: usize,
+    /// Objects merged before the batch
+    first: u32,
+    /// Objects before the start percentage, counted but not forwarded, they precede `objects`
+    skipped: u32,
+    objects: Vec<MergedObject>,
+}
+
+impl ObjectBatch {
+    fn new(seq: usize, first: u32) -> Self {
+        ObjectBatch { seq, first, skipped: 0, objects: Vec::with_capacity(BATCH_OBJECTS) }
+    }
+
+    fn len(&self) -> usize {
+        self.skipped as usize + self.objects.len()
+    }
+}
+
+/// Rows and counts decoded from an object batch, merged in batch order
+#[derive(Debug, Default)]
+struct Chunk {
+    seq: usize,
+    frames: Frames,
+    flexray: FlexRayFrames,
+    ethernet: EthernetFrames,
+    summary: ConvertSummary,
+    /// One per input, in measurement start order
+    files: Vec<FileSummary>,
+    /// Objects of the batch consumed, unreadable ones included
+    read: u32,
+    /// An object past the percentage or time window was found, the following ones are dropped
+    stop: bool,
+}
+
+impl Chunk {
+    fn new(seq: usize, signals: usize, files: usize) -> Self {
+        Chunk {
+            seq,
+            frames: Frames::with_signals(signals),
+            files: (0..files).map(|_| FileSummary::default()).collect(),
+            ..Default::default()
+        }
+    }
+
+    /// Decodes one object, returns false when it is past the time window
+    fn push(&mut self, data: &ObjectTypes, start_timestamp: DateTime<Utc>, opts: &ConvertOptions, dbc: Option<&Dbc>,
+            file: usize)
+        -> bool
+    {
+        let summary = &mut self.summary;
+        let file = &mut self.files[file];
+        if opts.events && let Some(event) = decode_event(data, start_timestamp) {
+            if let TimeWindow::Inside = time_window(event.ts, opts) {
+                summary.events.push(&event);
+            }
+            return true;
+        }
+        if opts.statistics && let Some(stat) = decode_bus_statistic(data, start_timestamp) {
+            if let TimeWindow::Inside = time_window(stat.ts, opts) {
+                summary.statistics.push(&stat);
+            }
+            return true;
+        }
+        if opts.lin && let Some(lin_frame) = decode_lin_message(data, start_timestamp) {
+            if let TimeWindow::Inside = time_window(lin_frame.ts, opts) {
+                summary.lin.push(&lin_frame);
+            }
+            // a LIN conversion still writes them to the main output
+            if opts.protocol != Protocol::Lin {
+                return true;
+            }
+        }
+
+        if opts.protocol == Protocol::FlexRay {
+            if let Some(frame) = decode_flexray_frame(data, start_timestamp) {
+                match time_window(frame.ts, opts) {
+                    TimeWindow::Before => summary.out_of_range += 1,
+                    TimeWindow::After => return false,
+                    TimeWindow::Inside if channel_match(frame.channel, opts) => {
+                        let pushed = !frame.null_frame && self.flexray.push(&frame);
+                        if pushed {
+                            file.add(frame.ts);
+                            summary.add_channel(frame.channel);
+                        } else {
+                            summary.flexray_skipped += 1;
+                        }
+                    }
+                    TimeWindow::Inside => summary.other_channel += 1,
+                }
+            } else {
+                summary.other_type += 1;
+            }
+            return true;
+        }
+
+        if opts.protocol == Protocol::Ethernet {
+            if let Some(frame) = decode_ethernet_frame(data, start_timestamp) {
+                match time_window(frame.ts, opts) {
+                    TimeWindow::Before => summary.out_of_range += 1,
+                    TimeWindow::After => return false,
+                    TimeWindow::Inside if channel_match(frame.channel, opts) => {
+                        self.ethernet.push(&frame);
+                        file.add(frame.ts);
+                        summary.add_channel(frame.channel);
+                    }
+                    TimeWindow::Inside => summary.other_channel += 1,
+                }
+            } else {
+                summary.other_type += 1;
+            }
+            return true;
+        }
+
+        let decoded = match opts.protocol {
+            Protocol::Lin => decode_lin_frame(data, start_timestamp),
+            _ => decode_frame(data, start_timestamp, opts.fd),
+        };
+        if let Some(frame) = decoded {
+            // data zero-padded to the DLC length with `DlcMismatch::Truncate`
+            let padded: Vec<u8>;
+            let mut frame = frame;
+            match time_window(frame.ts, opts) {
+                TimeWindow::Before => {
+                    summary.out_of_range += 1;
+                    return true;
+                }
+                TimeWindow::After => return false,
+                TimeWindow::Inside => {}
+            }
+            if !channel_match(frame.channel, opts) {
+                summary.other_channel += 1;
+            } else if let Some(masked) = id_match(frame.id, opts) && pgn_match(&frame, opts) {
+                let expected = dlc_len(frame.dlc, frame.fd);
+                if !frame.rtr && frame.data.len() != expected {
+                    summary.dlc_mismatch += 1;
+                    match opts.dlc_mismatch {
+                        DlcMismatch::Keep => {}
+                        DlcMismatch::Skip => return true,
+                        DlcMismatch::Truncate if frame.data.len() > expected => frame.data = &frame.data[..expected],
+                        DlcMismatch::Truncate => {
+                            padded = [frame.data, &vec![0; expected - frame.data.len()]].concat();
+                            frame.data = &padded;
+                        }
+                    }
+                }
+                if !dlc_match(frame.data, opts) {
+                    summary.other_dlc += 1;
+                    return true;
+                }
+                if !data_match(frame.data, opts) {
+                    summary.other_data += 1;
+                    return true;
+                }
+                summary.masked_id += masked as usize;
+                self.frames.push(&frame);
+                file.add(frame.ts);
+                summary.add_channel(frame.channel);
+                if let Some(dbc) = dbc {
+                    self.frames.push_signals(dbc.decode(frame.id, frame.data));
+                }
+                if frame.fd {
+                    summary.fd_frames += 1;
+                }
+            } else {
+                summary.other_id += 1;
+            }
+        } else if (opts.errors || opts.error_rows)
+                  && let Some(error_frame) = decode_error_frame(data, start_timestamp)
+        {
+            if let TimeWindow::Inside = time_window(error_frame.ts, opts) {
+                if opts.errors {
+                    summary.errors.push(&error_frame);
+                }
+                // the ID and payload filters do not apply, the recorded ID is often 0
+                if opts.error_rows && channel_match(error_frame.channel, opts) {
+                    self.frames.push_error(&error_frame);
+                    if dbc.is_some() {
+                        self.frames.push_signals(vec![None; self.frames.signals.len()]);
+                    }
+                    file.add(error_frame.ts);
+                    summary.add_channel(error_frame.channel);
+                    summary.error_rows += 1;
+                }
+            }
+        } else {
+            summary.other_type += 1;
+            if self.frames.is_empty() {
+                // only needed to explain an empty output
+                *summary.unsupported.entry(object_type_name(data)).or_insert(0) += 1;
+            }
+        }
+        true
+    }
+}
+
+/// Objects read from a BLF before they are handed to the merger
+const READ_OBJECTS: usize = 1024;
+
+/// Reads the objects of `blf` in batches until the end of the file or until the merger is gone.
+/// `corrupt` counts the unreadable objects skipped over every input.
+/// Returns true when the file is truncated, only with `ConvertOptions::best_effort`.
+fn read_objects<R: BufRead + Seek>(blf: BlfFile<R>, input: usize, corrupt: &AtomicUsize, opts: &ConvertOptions,
+                                   batches: SyncSender<Vec<Option<Object>>>)
+    -> Result<bool, String>
+{
+    let declared = blf.file_stats.object_count;
+    let mut blf_iter = blf.into_iter();
+    let mut batch = Vec::with_capacity(READ_OBJECTS);
+    let mut c = 0;
+
+    let end = loop {
+        let next = if opts.best_effort || opts.max_errors.is_some() {
+            // a truncated or malformed object panics inside the reader
+            match panic::catch_unwind(AssertUnwindSafe(|| blf_iter.next())) {
+                Ok(next) => next.map(Some),
+                Err(_) if opts.max_errors.is_some_and(|max| {
+                    corrupt.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| (n < max).then_some(n + 1))
+                           .is_ok()
+                }) => Some(None),
+                Err(_) if opts.best_effort => break Ok(true),
+                Err(_) => break Err(format!("More than {} unreadable objects, giving up at object {} of file {}",
+                                            corrupt.load(Ordering::Relaxed), c + 1, input)),
+            }
+        } else {
+            blf_iter.next().map(Some)
+        };
+        let Some(object) = next else {
+            break Ok(opts.best_effort && c < declared);
+        };
+        c += 1;
+        batch.push(object);
+        if batch.len() == READ_OBJECTS
+           && batches.send(mem::replace(&mut batch, Vec::with_capacity(READ_OBJECTS))).is_err() {
+            // past the window, the remaining objects are not needed
+            return Ok(false);
+        }
+    };
+    if !batch.is_empty() {
+        let _ = batches.send(batch);
+    }
+
+    end
+}
+
+/// Objects of one file waiting to be merged
+struct Source<'scope> {
+    start_timestamp: DateTime<Utc>,
+    batches: Receiver<Vec<Option<Object>>>,
+    reader: Option<ScopedJoinHandle<'scope, Result<bool, String>>>,
+    pending: VecDeque<Option<Object>>,
+    /// Next object of the file
+    head: Option<Option<Object>>,
+    /// Time of the last object with a known type, objects without one keep their place after it
+    last_ts: i64,
+}
+
+impl Source<'_> {
+    /// Moves the next object of the file to `head`, false once the file is read
+    fn advance(&mut self) -> bool {
+        if self.pending.is_empty() && let Ok(batch) = self.batches.recv() {
+            self.pending.extend(batch);
+        }
+        let Some(object) = self.pending.pop_front() else {
+            return false;
+        };
+        if let Some(ts) = object.as_ref().and_then(|obj| object_timestamp(&obj.data, self.start_timestamp)) {
+            self.last_ts = ts;
+        }
+        self.head = Some(object);
+        true
+    }
+
+    /// Result of the reader once the file is read
+    fn finish(&mut self) -> Result<bool, String> {
+        match self.reader.take().map(ScopedJoinHandle::join) {
+            Some(Ok(end)) => end,
+            Some(Err(payload)) => panic::resume_unwind(payload),
+            None => Ok(false),
+        }
+    }
+}
+
+/// Merges the objects of `sources` by timestamp, the earliest file first on equal timestamps.
+/// Objects before the start percentage are only counted, the decoders never see them.
+/// Returns the objects merged before the first truncated file ended, only with `ConvertOptions::best_effort`.
+fn merge_objects(mut sources: Vec<Source>, objects: u32, opts: &ConvertOptions, batches: SyncSender<ObjectBatch>)
+    -> Result<Option<u32>, String>
+{
+    let mut batch = ObjectBatch::new(0, 0);
+    let mut truncated = None;
+    let mut c = 0;
+
+    // heads of the files, the earliest on top
+    let mut heap = BinaryHeap::with_capacity(sources.len());
+    let mut end = Ok(());
+    for (file, source) in sources.iter_mut().enumerate() {
+        if source.advance() {
+            heap.push(Reverse((source.last_ts, file)));
+        } else {
+            match source.finish() {
+                Ok(true) => {
+                    truncated.get_or_insert(c);
+                }
+                Ok(false) => {}
+                Err(error) => end = Err(error),
+            }
+        }
+    }
+
+    while end.is_ok() && let Some(Reverse((_, file))) = heap.pop() {
+        let source = &mut sources[file];
+        let object = source.head.take().flatten();
+        c += 1;
+        let perc = ((c as f64) / (objects as f64)) * 100.0;
+        if object.is_some() && perc < opts.start_percentage {
+            batch.skipped += 1;
+        } else {
+            batch.objects.push(MergedObject { file, object });
+        }
+        // skipped objects are sent too, as counts, so the progress keeps moving
+        if batch.len() == BATCH_OBJECTS {
+            let seq = batch.seq + 1;
+            let full = mem::replace(&mut batch, ObjectBatch::new(seq, c));
+            if batches.send(full).is_err() {
+                // past the window, the readers stop once their channel is dropped
+                return Ok(None);
+            }
+        }
+
+        if source.advance() {
+            heap.push(Reverse((source.last_ts, file)));
+        } else {
+            match source.finish() {
+                Ok(true) => {
+                    truncated.get_or_insert(c);
+                }
+                Ok(false) => {}
+                Err(error) => end = Err(error),
+            }
+        }
+    }
+    if batch.len() > 0 {
+        let _ = batches.send(batch);
+    }
+
+    end.map(|()| truncated)
+}
+
+/// Turns the batches of `batches` into chunks until the merger or the writer is done
+fn decode_objects(batches: &Mutex<Receiver<ObjectBatch>>, chunks: &SyncSender<Chunk>, starts: &[DateTime<Utc>],
+                  objects: u32, opts: &ConvertOptions, dbc: Option<&Dbc>)
+{
+    let signals = dbc.map_or(0, |dbc| dbc.columns().len());
+    loop {
+        // the lock is released before decoding, the other decoders take the next batches meanwhile
+        let next = match batches.lock() {
+            Ok(receiver) => receiver.recv(),
+            Err(_) => return,
+        };
+        let Ok(batch) = next else {
+            return;
+        };
+
+        let decoding = Instant::now();
+        let mut chunk = Chunk::new(batch.seq, signals, starts.len());
+        chunk.read = batch.skipped;
+        chunk.summary.out_of_range = batch.skipped as usize;
+        for merged in &batch.objects {
+            chunk.read += 1;
+            let Some(obj) = &merged.object else {
+                chunk.summary.corrupt += 1;
+                continue;
+            };
+            let perc = (((batch.first + chunk.read) as f64) / (objects as f64)) * 100.0;
+            if perc < opts.start_percentage {
+                chunk.summary.out_of_range += 1;
+                continue;
+            }
+            if perc > opts.end_percentage || !chunk.push(&obj.data, starts[merged.file], opts, dbc, merged.file) {
+                chunk.stop = true;
+                break;
+            }
+        }
+        chunk.summary.decode_time = decoding.elapsed();
+        if chunks.send(chunk).is_err() {
+            return;
+        }
+    }
+}
+
+/// Options with `start_seconds` and `end_seconds` turned into absolute times from `start`, the measurement
+/// start of the earliest file, and intersected with `start_time` and `end_time`
+fn relative_window(opts: &ConvertOptions, start: Option<DateTime<Utc>>) -> Cow<'_, ConvertOptions> {
+    let Some(start) = start.and_then(|start| start.timestamp_nanos_opt()) else {
+        return Cow::Borrowed(opts);
+    };
+    if opts.start_seconds.is_none() && opts.end_seconds.is_none() {
+        return Cow::Borrowed(opts);
+    }
+    let absolute = |seconds: f64| start.saturating_add((seconds * 1e9).round() as i64);
+    let mut opts = opts.clone();
+    if let Some(seconds) = opts.start_seconds {
+        opts.start_time = Some(opts.start_time.map_or(absolute(seconds), |ts| ts.max(absolute(seconds))));
+    }
+    if let Some(seconds) = opts.end_seconds {
+        opts.end_time = Some(opts.end_time.map_or(absolute(seconds), |ts| ts.min(absolute(seconds))));
+    }
+    Cow::Owned(opts)
+}
+
+/// Options for the merger and the decoders: with `PercentOf::Matched` the percentages apply to the frames in the
+/// writer, every object is decoded
+fn object_window(opts: &ConvertOptions) -> Cow<'_, ConvertOptions> {
+    if opts.percent_of == PercentOf::Objects {
+        return Cow::Borrowed(opts);
+    }
+    Cow::Owned(ConvertOptions { start_percentage: 0.0, end_percentage: 100.0, ..opts.clone() })
+}
+
+/// Drops the frames outside the percentage window over `total` matched frames, `matched` counts the frames
+/// before the chunk. Returns the frames dropped and true once the window is over.
+fn matched_window(frames: &mut Frames, matched: &mut usize, total: usize, opts: &ConvertOptions,
+                  channels: &mut BTreeMap<u16, usize>)
+    -> (usize, bool)
+{
+    let perc = |index: usize| ((*matched + index + 1) as f64 / total.max(1) as f64) * 100.0;
+    let window = opts.start_percentage..=opts.end_percentage;
+    let keep: Vec<bool> = (0..frames.len()).map(|index| window.contains(&perc(index))).collect();
+    let past = !frames.is_empty() && perc(frames.len() - 1) > opts.end_percentage;
+    *matched += frames.len();
+    (drop_rows(frames, &keep, channels), past)
+}
+
+/// Drops the frames of an ID logged less than `interval` nanoseconds after its last kept frame, `last_kept`
+/// holds that frame's timestamp across the chunks. Returns the frames dropped.
+fn downsample(frames: &mut Frames, interval: i64, last_kept: &mut HashMap<u32, i64>,
+              channels: &mut BTreeMap<u16, usize>) -> usize
+{
+    let rows = frames.ts.iter().zip(&frames.id).zip(&frames.is_error);
+    // error rows are always kept
+    let keep: Vec<bool> = rows.map(|((ts, id), is_error)| match last_kept.get(id) {
+        _ if *is_error => true,
+        Some(last) if ts - last < interval => false,
+        _ => {
+            last_kept.insert(*id, *ts);
+            true
+        }
+    }).collect();
+    drop_rows(frames, &keep, channels)
+}
+
+/// Drops the frames whose data equals the one of the last kept frame of their ID, unless `heartbeat`
+/// nanoseconds passed since it. `last_kept` holds that frame's timestamp and data across the chunks.
+/// Returns the frames dropped.
+fn drop_unchanged(frames: &mut Frames, heartbeat: Option<i64>, last_kept: &mut HashMap<u32, (i64, Vec<u8>)>,
+                  channels: &mut BTreeMap<u16, usize>) -> usize
+{
+    let mut keep = Vec::with_capacity(frames.ts.len());
+    let mut offset = 0;
+    for (((ts, id), len), is_error) in frames.ts.iter().zip(&frames.id).zip(&frames.len).zip(&frames.is_error) {
+        let data = &frames.data[offset..offset + len];
+        offset += len;
+        if *is_error {
+            keep.push(true);
+            continue;
+        }
+        let unchanged = last_kept.get(id).is_some_and(|(last_ts, last_data)| {
+            last_data.as_slice() == data && heartbeat.is_none_or(|heartbeat| ts - last_ts < heartbeat)
+        });
+        if !unchanged {
+            last_kept.insert(*id, (*ts, data.to_vec()));
+        }
+        keep.push(!unchanged);
+    }
+    drop_rows(frames, &keep, channels)
+}
+
+/// Key-value metadata recording the transformations dropping frames by their data, readers can tell
+/// the file is not a complete log
+fn transformations(opts: &ConvertOptions) -> Vec<KeyValue> {
+    let mut metadata = Vec::new();
+    if opts.on_change {
+        let value = match opts.on_change_heartbeat {
+            Some(heartbeat) => format!("heartbeat={}s", heartbeat.as_secs_f64()),
+            None => "true".to_string(),
+        };
+        metadata.push(KeyValue::new("parquet2peak.on_change".to_string(), value));
+    }
+    if let Some(interval) = opts.min_interval {
+        metadata.push(KeyValue::new("parquet2peak.min_interval_ms".to_string(), interval.as_millis().to_string()));
+    }
+    metadata
+}
+
+/// Keeps the frames whose `keep` entry is true, the dropped ones are taken off their channel count
+fn drop_rows(frames: &mut Frames, keep: &[bool], channels: &mut BTreeMap<u16, usize>) -> usize {
+    let dropped = keep.iter().filter(|kept| !**kept).count();
+    if dropped > 0 {
+        for (channel, _) in frames.channel.iter().zip(keep).filter(|(_, kept)| !**kept) {
+            if let Some(count) = channels.get_mut(channel) {
+                *count -= 1;
+            }
+        }
+        frames.retain(keep);
+    }
+    dropped
+}
+
+/// Decoders used when `ConvertOptions::decode_threads` is not set, one core is left to the readers and one to the
+/// writer
+fn default_decode_threads() -> usize {
+    thread::available_parallelism().map_or(1, |cores| cores.get().saturating_sub(2).max(1))
+}
+
+/// Converts the BLF read from `reader` into parquet written to `writer`, the reader seeks back over corrupt objects
+pub fn blf_to_parquet<R, W>(reader: R, writer: W, opts: &ConvertOptions)
+    -> Result<ConvertSummary, Error>
+where
+    R: BufRead + Seek + Send,
+    W: Write + Send,
+{
+    blfs_to_parquet(vec![reader], writer, opts)
+}
+
+/// Converts the BLFs read from `readers` into one parquet, their objects merged in timestamp order.
+/// Percentage and channel filters apply to the merged stream.
+///
+/// The conversion is a pipeline: a thread per file reads the objects in batches, a merger interleaves
+/// them by timestamp, the decoders turn the merged batches into rows and the calling thread writes them
+/// back in merged order. The channels between the stages are bounded, so memory use does not depend on
+/// the file sizes.
+pub fn blfs_to_parquet<R, W>(readers: Vec<R>, writer: W, opts: &ConvertOptions)
+    -> Result<ConvertSummary, Error>
+where
+    R: BufRead + Seek + Send,
+    W: Write + Send,
+{
+    convert_blfs(readers, Target::Writer(writer), opts)
+}
+
+/// Same as `blfs_to_parquet` with a parquet per channel, opened by `open` with the channel (numbered as the
+/// channel column) when its first rows are written. CAN and LIN only, channels without frames get no file.
+pub fn blfs_to_parquet_by_channel<R, W, F>(readers: Vec<R>, open: F, opts: &ConvertOptions)
+    -> Result<ConvertSummary, Error>
+where
+    R: BufRead + Seek + Send,
+    W: Write + Send,
+    F: FnMut(u16) -> Result<W, Error>,
+{
+    if !matches!(opts.protocol, Protocol::Can | Protocol::Lin) || opts.format != Format::Parquet {
+        return Err(Error::Options("Only CAN and LIN frames can be split by channel, in parquet".to_string()));
+    }
+    convert_blfs(readers, Target::Channels(Box::new(open)), opts)
+}
+
+/// Frames of `readers` kept by the channel, ID, payload and time filters, the `ConvertOptions::matched_frames`
+/// of the same inputs. The percentages, downsampling and frame limit are ignored and nothing is written.
+pub fn count_matched<R>(readers: Vec<R>, opts: &ConvertOptions) -> Result<usize, Error>
+where
+    R: BufRead + Seek + Send,
+{
+    let opts = ConvertOptions {
+        start_percentage: 0.0,
+        end_percentage: 100.0,
+        percent_of: PercentOf::Objects,
+        min_interval: None,
+        on_change: false,
+        max_frames: None,
+        errors: false,
+        events: false,
+        statistics: false,
+        lin: false,
+        dbc: None,
+        ..opts.clone()
+    };
+    convert_blfs::<R, io::Sink>(readers, Target::Discard, &opts).map(|summary| summary.rows)
+}
+
+fn convert_blfs<R, W>(readers: Vec<R>, target: Target<'_, W>, opts: &ConvertOptions)
+    -> Result<ConvertSummary, Error>
+where
+    R: BufRead + Seek + Send,
+    W: Write + Send,
+{
+    if opts.batch_size == 0 {
+        return Err(Error::Options("Batch size must be greater than 0".to_string()));
+    }
+    let can_or_lin = matches!(opts.protocol, Protocol::Can | Protocol::Lin);
+    if (opts.min_interval.is_some() || opts.on_change) && !can_or_lin {
+        return Err(Error::Options("Downsampling by ID only applies to CAN and LIN frames".to_string()));
+    }
+    if (!opts.data_match.is_empty() || opts.min_dlc.is_some() || opts.max_dlc.is_some()) && !can_or_lin {
+        return Err(Error::Options("Payload filters only apply to CAN and LIN frames".to_string()));
+    }
+    if opts.dlc_mismatch != DlcMismatch::Keep && !can_or_lin {
+        return Err(Error::Options("DLC mismatch handling only applies to CAN and LIN frames".to_string()));
+    }
+    if !opts.pgns.is_empty() && opts.protocol != Protocol::Can {
+        return Err(Error::Options("PGN filters only apply to CAN frames".to_string()));
+    }
+    if opts.percent_of == PercentOf::Matched && !can_or_lin {
+        return Err(Error::Options("A percentage of matched frames only applies to CAN and LIN frames".to_string()));
+    }
+    if opts.percent_of == PercentOf::Matched && opts.matched_frames.is_none() {
+        return Err(Error::Options("A percentage of matched frames needs their count, see count_matched"
+                                      .to_string()));
+    }
+    if opts.max_frames == Some(0) {
+        return Err(Error::Options("Max frames must be greater than 0".to_string()));
+    }
+    if opts.decode_threads == Some(0) {
+        return Err(Error::Options("Decode threads must be greater than 0".to_string()));
+    }
+    if opts.format == Format::Csv && !matches!(opts.protocol, Protocol::Can | Protocol::Lin) {
+        return Err(Error::Options("CSV output only holds CAN and LIN frames".to_string()));
+    }
+    if let (Some(start), Some(end)) = (opts.start_seconds, opts.end_seconds) && start >= end {
+        return Err(Error::Options(format!("End seconds {} must be greater than start seconds {}", end, start)));
+    }
+    if opts.format == Format::Csv && opts.dbc.is_some() {
+        return Err(Error::Options("DBC signals cannot be written to CSV, use the parquet output".to_string()));
+    }
+    if opts.error_rows && (opts.protocol != Protocol::Can || opts.format == Format::Csv) {
+        return Err(Error::Options("Error rows are only written with CAN frames to parquet or Arrow IPC".to_string()));
+    }
+
+    let start = Instant::now();
+    let mut blfs = Vec::with_capacity(readers.len());
+    for (input, reader) in readers.into_iter().enumerate() {
+        let blf = match BlfFile::from_reader(reader) {
+            Ok(b) => b,
+            Err((error, _)) => return Err(Error::Blf(format!("file {}: {}", input, error))),
+        };
+        // each file has its own base for the object timestamps
+        let dt = blf.file_stats.measurement_start_time()
+                    .ok_or_else(|| Error::Blf(format!("file {}: invalid measurement start time", input)))?;
+        blfs.push((input, Utc.from_utc_datetime(&dt), blf));
+    }
+    // rolled over logs can be passed in any order
+    blfs.sort_by_key(|(_, start_timestamp, _)| *start_timestamp);
+    let window = relative_window(opts, blfs.first().map(|(_, start_timestamp, _)| *start_timestamp));
+    let opts = &*window;
+    let objects_opts = object_window(opts);
+    let duration = start.elapsed();
+    let message = |text: String| if opts.messages_to_stderr { eprintln!("{}", text) } else { println!("{}", text) };
+    message(format!("Convert from file: {:?}", duration));
+
+    let extra = ExtraColumns {
+        rtr: opts.protocol == Protocol::Can,
+        ext: opts.protocol == Protocol::Can,
+        fd: opts.fd && opts.protocol == Protocol::Can,
+        dir: matches!(opts.protocol, Protocol::Can | Protocol::Lin),
+        errors: opts.error_rows,
+    };
+    let schema = match opts.protocol {
+        Protocol::FlexRay => flexray_schema(opts.ts_type),
+        Protocol::Ethernet => ethernet_schema(opts.ts_type),
+        _ => frame_schema(extra, opts.ts_type),
+    };
+    let dbc = opts.dbc.as_ref().filter(|_| opts.protocol == Protocol::Can);
+    let schema = match dbc {
+        Some(dbc) => with_signal_fields(schema, dbc.columns()),
+        None => schema,
+    };
+    let mut writer = Output::new(target, &schema, opts)?;
+    let mut frames = Frames::with_signals(dbc.map_or(0, |dbc| dbc.columns().len()));
+    let mut flexray = FlexRayFrames::default();
+    let mut ethernet = EthernetFrames::default();
+    let mut summary = ConvertSummary::default();
+    let mut c = 0;
+
+    let objects: u32 = blfs.iter().map(|(_, _, blf)| blf.file_stats.object_count).sum();
+    summary.objects = objects;
+    // the percentage window is based on the header object count, a streamed BLF can leave it unset
+    if objects == 0 && (opts.start_percentage > 0.0 || opts.end_percentage < 100.0) {
+        return Err(Error::Options("The BLF header has no object count, the percentage filter cannot be applied \
+                                   (use --start-time/--end-time instead)".to_string()));
+    }
+
+    let channels = if opts.channels.is_empty() {
+        "all".to_string()
+    } else {
+        // as shown in CANoe, the channel column holds them minus 1
+        opts.channels.iter().map(|channel| channel.to_string()).collect::<Vec<_>>().join(",")
+    };
+    let unit = match opts.percent_of {
+        PercentOf::Objects => "of the objects".to_string(),
+        PercentOf::Matched => format!("of {} matched frames", opts.matched_frames.unwrap_or_default()),
+    };
+    message(format!("Filtering {} on BLF channel {} and from {}% to {}% {}", objects, channels,
+                    opts.start_percentage, opts.end_percentage, unit));
+
+    let decoders = opts.decode_threads.unwrap_or_else(default_decode_threads);
+    let bar = progress_bar(u64::from(objects), opts.progress);
+    let starts: Vec<DateTime<Utc>> = blfs.iter().map(|(_, start_timestamp, _)| *start_timestamp).collect();
+    let mut files: Vec<FileSummary> = blfs.iter()
+                                          .map(|(input, _, blf)| FileSummary {
+                                              input: *input,
+                                              objects: blf.file_stats.object_count,
+                                              ..Default::default()
+                                          })
+                                          .collect();
+    let corrupt = AtomicUsize::new(0);
+    // percentage or time window past its end
+    let mut stop = false;
+    let mut matched = 0;
+    // percentage window over the matched frames past its end, the objects left are not read
+    let mut matched_done = false;
+
+    let min_interval = opts.min_interval.map(|interval| interval.as_nanos() as i64);
+    let mut last_kept = HashMap::new();
+    let heartbeat = opts.on_change_heartbeat.map(|heartbeat| heartbeat.as_nanos() as i64);
+    let mut last_changed = HashMap::new();
+    // timestamp of the last frame appended, for `ConvertOptions::monotonic`
+    let mut last_ts: Option<i64> = None;
+    let pipeline = Instant::now();
+    let merged = thread::scope(|scope| -> Result<_, Error> {
+        let mut sources = Vec::with_capacity(blfs.len());
+        for (input, start_timestamp, blf) in blfs {
+            // two batches per file are enough for the merger to pick the earliest object
+            let (read_tx, read_rx) = sync_channel(2);
+            let corrupt = &corrupt;
+            let opts = &*objects_opts;
+            let reader = scope.spawn(move || read_objects(blf, input, corrupt, opts, read_tx));
+            sources.push(Source {
+                start_timestamp,
+                batches: read_rx,
+                reader: Some(reader),
+                pending: VecDeque::new(),
+                head: None,
+                last_ts: start_timestamp.timestamp_nanos_opt().unwrap_or_default(),
+            });
+        }
+
+        // a few batches per decoder keep them busy without buffering the files
+        let (batch_tx, batch_rx) = sync_channel(decoders * 2);
+        let (chunk_tx, chunk_rx) = sync_channel(decoders * 2);
+        let merger = {
+            let opts = &*objects_opts;
+            scope.spawn(move || merge_objects(sources, objects, opts, batch_tx))
+        };
+        let batch_rx = Arc::new(Mutex::new(batch_rx));
+        for _ in 0..decoders {
+            let batch_rx = Arc::clone(&batch_rx);
+            let chunk_tx = chunk_tx.clone();
+            let starts = &starts;
+            let opts = &*objects_opts;
+            scope.spawn(move || decode_objects(&batch_rx, &chunk_tx, starts, objects, opts, dbc));
+        }
+        // the merger stops once every decoder is gone, and the decoders once the writer is
+        drop(batch_rx);
+        drop(chunk_tx);
+
+        // chunks arrive in any order, they are written in batch order
+        let mut pending = BTreeMap::new();
+        let mut next = 0;
+        'merge: for chunk in chunk_rx.iter() {
+            pending.insert(chunk.seq, chunk);
+            while let Some(mut chunk) = pending.remove(&next) {
+                let writing = Instant::now();
+                next += 1;
+                c += chunk.read;
+                bar.set_position(u64::from(c));
+                if summary.rows == 0 && frames.is_empty() {
+                    for (name, count) in mem::take(&mut chunk.summary.unsupported) {
+                        *summary.unsupported.entry(name).or_insert(0) += count;
+                    }
+                }
+                summary.merge(mem::take(&mut chunk.summary));
+                for (file, chunk_file) in files.iter_mut().zip(&chunk.files) {
+                    file.merge(chunk_file);
+                }
+                // the decoders run in parallel, the frame positions and the last kept frame of each ID are
+                // only known here
+                if opts.percent_of == PercentOf::Matched && let Some(total) = opts.matched_frames {
+                    let (dropped, past) = matched_window(&mut chunk.frames, &mut matched, total, opts,
+                                                         &mut summary.channels);
+                    summary.out_of_range += dropped;
+                    matched_done = past;
+                }
+                if opts.on_change {
+                    summary.unchanged += drop_unchanged(&mut chunk.frames, heartbeat, &mut last_changed,
+                                                        &mut summary.channels);
+                }
+                if let Some(interval) = min_interval {
+                    summary.downsampled += downsample(&mut chunk.frames, interval, &mut last_kept,
+                                                      &mut summary.channels);
+                }
+                if opts.monotonic != Monotonic::Off {
+                    // only the buffer of the converted protocol has frames
+                    let first = summary.rows + frames.len() + flexray.len() + ethernet.len();
+                    let ts = chunk.frames.ts.iter().chain(&chunk.flexray.ts).chain(&chunk.ethernet.ts);
+                    for (index, ts) in ts.enumerate() {
+                        if let Some(previous) = last_ts && *ts < previous {
+                            let text = format!("Timestamp going back by {:?} at frame {} in BLF order",
+                                               Duration::from_nanos((previous - ts) as u64), first + index);
+                            if opts.monotonic == Monotonic::Error {
+                                return Err(Error::Blf(text));
+                            }
+                            bar.suspend(|| eprintln!("Warning: {}", text));
+                            summary.non_monotonic += 1;
+                        }
+                        last_ts = Some(*ts);
+                    }
+                }
+                let appended = frames.len();
+                frames.append(&mut chunk.frames);
+                flexray.append(&mut chunk.flexray);
+                ethernet.append(&mut chunk.ethernet);
+                // only the buffer of the converted protocol has frames
+                if let Some(max) = opts.max_frames
+                    && summary.rows + frames.len() + flexray.len() + ethernet.len() >= max
+                {
+                    let keep = max - summary.rows;
+                    frames.truncate(keep);
+                    flexray.truncate(keep);
+                    ethernet.truncate(keep);
+                    summary.limit_reached = true;
+                }
+                // collected here, the filters above and --max-frames drop frames the decoders kept
+                let kept = frames.id.iter().zip(&frames.is_error).skip(appended).filter(|(_, is_error)| !**is_error);
+                summary.ids.extend(kept.map(|(id, _)| *id));
+
+                if frames.len() >= opts.batch_size {
+                    summary.rows += flush(&mut writer, &schema, &mut frames)?;
+                }
+                if flexray.len() >= opts.batch_size {
+                    summary.rows += flush(&mut writer, &schema, &mut flexray)?;
+                }
+                if ethernet.len() >= opts.batch_size {
+                    summary.rows += flush(&mut writer, &schema, &mut ethernet)?;
+                }
+                summary.write_time += writing.elapsed();
+                if chunk.stop {
+                    stop = true;
+                    break 'merge;
+                }
+                if summary.limit_reached || matched_done {
+                    break 'merge;
+                }
+            }
+        }
+        drop(chunk_rx);
+
+        match merger.join() {
+            Ok(merged) => Ok(merged),
+            Err(payload) => panic::resume_unwind(payload),
+        }
+    })?;
+
+    // the reader errors are only meaningful for the objects inside the window
+    if !stop && !summary.limit_reached && !matched_done && let Some(truncated) = merged.map_err(Error::Blf)? {
+        summary.truncated = Some(truncated);
+    }
+    summary.files = files;
+
+    if stop {
+        // the object past the window and the ones not read after it
+        summary.out_of_range += objects.saturating_sub(c) as usize + 1;
+    }
+
+    let writing = Instant::now();
+    summary.rows += flush(&mut writer, &schema, &mut frames)?;
+    summary.rows += flush(&mut writer, &schema, &mut flexray)?;
+    summary.rows += flush(&mut writer, &schema, &mut ethernet)?;
+
+    writer.close(&transformations(opts))?;
+    summary.write_time += writing.elapsed();
+    summary.pipeline_time = pipeline.elapsed();
+    bar.finish_with_message(format!("{} rows", summary.rows));
+
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use super::*;
+    use crate::blf_writer::BlfWriter;
+
+    const START: i64 = 1_718_445_005_000_000_000;
+    const OBJ_TYPE_APP_TEXT: u32 = 65;
+
+    /// 100 objects 1 ms apart, CAN makes up 10% of them: objects 1, 11, ..., 91 are frames with the IDs 0 to 9,
+    /// the others APP_TEXT
+    fn sparse_can() -> Vec<u8> {
+        let mut blf = Cursor::new(Vec::new());
+        let mut writer = BlfWriter::new(&mut blf, START).unwrap();
+        // source, reserved, text length, reserved, text
+        let text: Vec<u8> = [0u32, 0, 4, 0].iter().flat_map(|field| field.to_le_bytes()).chain(*b"note").collect();
+        for object in 0..100 {
+            let ts = START + object * 1_000_000;
+            if object % 10 == 0 {
+                writer.write_can_message(ts, 1, (object / 10) as u32, 0, &[0]).unwrap();
+            } else {
+                writer.write_object(OBJ_TYPE_APP_TEXT, ts, &text).unwrap();
+            }
+        }
+        writer.finish().unwrap();
+        blf.into_inner()
+    }
+
+    /// Frames on BLF channel 1 from `(ts in ms, id)` pairs
+    fn frames(rows: &[(i64, u32)]) -> Frames {
+        let mut frames = Frames::default();
+        for &(ms, id) in rows {
+            frames.push(&Frame {
+                ts: ms * 1_000_000,
+                channel: 1,
+                id,
+                ext: false,
+                data: &[0],
+                dlc: 1,
+                rtr: false,
+                fd: false,
+                brs: false,
+                esi: false,
+                dir: 0,
+            });
+        }
+        frames
+    }
+
+    #[test]
+    fn downsample_across_chunks() {
+        let interval = 10_000_000;
+        let mut last_kept = HashMap::new();
+        let mut channels = BTreeMap::from([(0, 8)]);
+        let mut first = frames(&[(0, 0x100), (5, 0x100), (8, 0x200), (12, 0x100)]);
+        assert_eq!(downsample(&mut first, interval, &mut last_kept, &mut channels), 1);
+        assert_eq!(first.ts, [0, 8_000_000, 12_000_000]);
+        // 0x100 and 0x200 were last kept at 12 and 8 ms in the previous chunk
+        let mut second = frames(&[(15, 0x100), (17, 0x200), (22, 0x100), (30, 0x300)]);
+        assert_eq!(downsample(&mut second, interval, &mut last_kept, &mut channels), 2);
+        assert_eq!(second.id, [0x100, 0x300]);
+        assert_eq!(second.ts, [22_000_000, 30_000_000]);
+        assert_eq!(channels, BTreeMap::from([(0, 5)]));
+    }
+
+    #[test]
+    fn matched_window_across_chunks() {
+        let opts = ConvertOptions { start_percentage: 25.0, end_percentage: 50.0, ..Default::default() };
+        let mut matched = 0;
+        let mut channels = BTreeMap::new();
+        // 10%, 20%, 30% and 40% of the 10 frames
+        let mut first = frames(&[(0, 0), (1, 1), (2, 2), (3, 3)]);
+        assert_eq!(matched_window(&mut first, &mut matched, 10, &opts, &mut channels), (2, false));
+        assert_eq!(first.id, [2, 3]);
+        // 50% is the last frame kept, the window is over
+        let mut second = frames(&[(4, 4), (5, 5), (6, 6), (7, 7)]);
+        assert_eq!(matched_window(&mut second, &mut matched, 10, &opts, &mut channels), (3, true));
+        assert_eq!(second.id, [4]);
+        assert_eq!(matched, 8);
+    }
+
+    #[test]
+    fn percent_of_objects_counts_every_object() {
+        let opts = ConvertOptions { start_percentage: 75.0, ..Default::default() };
+        let summary = blf_to_parquet(Cursor::new(sparse_can()), Vec::new(), &opts).unwrap();
+        // objects 81 and 91
+        assert_eq!(summary.rows, 2);
+        assert_eq!(summary.ids, BTreeSet::from([8, 9]));
+    }
+
+    #[test]
+    fn percent_of_matched_counts_the_frames() {
+        let opts = ConvertOptions { start_percentage: 75.0, percent_of: PercentOf::Matched, ..Default::default() };
+        let total = count_matched(vec![Cursor::new(sparse_can())], &opts).unwrap();
+        assert_eq!(total, 10);
+        let opts = ConvertOptions { matched_frames: Some(total), ..opts };
+        let summary = blf_to_parquet(Cursor::new(sparse_can()), Vec::new(), &opts).unwrap();
+        // the last 3 of the 10 frames
+        assert_eq!(summary.rows, 3);
+        assert_eq!(summary.ids, BTreeSet::from([7, 8, 9]));
+    }
+
+    #[test]
+    fn max_frames_lists_the_written_ids() {
+        let mut blf = Cursor::new(Vec::new());
+        let mut writer = BlfWriter::new(&mut blf, START).unwrap();
+        for id in 0..10 {
+            writer.write_can_message(START + i64::from(id) * 1_000_000, 1, id, 0, &[0]).unwrap();
+        }
+        writer.finish().unwrap();
+        let opts = ConvertOptions { max_frames: Some(3), ..Default::default() };
+        let summary = blf_to_parquet(Cursor::new(blf.into_inner()), Vec::new(), &opts).unwrap();
+        assert!(summary.limit_reached);
+        assert_eq!(summary.rows, 3);
+        assert_eq!(summary.ids, BTreeSet::from([0, 1, 2]));
+    }
+}
@@ -0,0 +1,207 @@
+//! Signal decoding with a DBC file
+
+use std::{collections::HashMap, path::Path};
+use can_dbc::{ByteOrder, DBC, MessageId, MultiplexIndicator, ValueType};
+use crate::{blf::CAN_ID_MASK, error::Error};
+
+#[derive(Debug, Clone)]
+struct SignalSpec {
+    /// Index in `Dbc::columns`
+    column: usize,
+    start_bit: u64,
+    size: u64,
+    little_endian: bool,
+    signed: bool,
+    factor: f64,
+    offset: f64,
+    /// Multiplexed signals are only decoded when the multiplexor has this value
+    mux_value: Option<u64>,
+    is_multiplexor: bool,
+}
+
+/// Signals of a DBC, one column per distinct signal name
+#[derive(Debug, Clone, Default)]
+pub struct Dbc {
+    columns: Vec<String>,
+    messages: HashMap<u32, Vec<SignalSpec>>,
+}
+
+impl Dbc {
+    pub fn from_file(path: &Path) -> Result<Self, Error> {
+        let content = std::fs::read(path).map_err(|error| Error::file(path, error))?;
+        Self::from_slice(&content).map_err(|error| match error {
+            Error::Dbc(message) => Error::Dbc(format!("{}: {}", path.display(), message)),
+            error => error,
+        })
+    }
+
+    pub fn from_slice(content: &[u8]) -> Result<Self, Error> {
+        let dbc = DBC::from_slice(content).map_err(|error| Error::Dbc(format!("{:?}", error)))?;
+        let mut columns: Vec<String> = Vec::new();
+        let mut messages = HashMap::new();
+
+        for message in dbc.messages() {
+            let id = match message.message_id() {
+                MessageId::Standard(id) => u32::from(*id),
+                MessageId::Extended(id) => *id,
+            } & CAN_ID_MASK;
+
+            let specs = message.signals().iter().map(|signal| {
+                // the same signal name in two messages shares the column
+                let column = match columns.iter().position(|name| name == signal.name()) {
+                    Some(column) => column,
+                    None => {
+                        columns.push(signal.name().clone());
+                        columns.len() - 1
+                    }
+                };
+                let (mux_value, is_multiplexor) = match signal.multiplexer_indicator() {
+                    MultiplexIndicator::Plain => (None, false),
+                    MultiplexIndicator::Multiplexor => (None, true),
+                    MultiplexIndicator::MultiplexedSignal(value) => (Some(*value), false),
+                    MultiplexIndicator::MultiplexorAndMultiplexedSignal(value) => (Some(*value), true),
+                };
+                SignalSpec {
+                    column,
+                    start_bit: *signal.start_bit(),
+                    size: *signal.signal_size(),
+                    little_endian: *signal.byte_order() == ByteOrder::LittleEndian,
+                    signed: *signal.value_type() == ValueType::Signed,
+                    factor: *signal.factor(),
+                    offset: *signal.offset(),
+                    mux_value,
+                    is_multiplexor,
+                }
+            }).collect();
+            messages.insert(id, specs);
+        }
+
+        Ok(Dbc { columns, messages })
+    }
+
+    /// Names of the decoded columns, in schema order
+    pub fn columns(&self) -> &[String] {
+        &self.columns
+    }
+
+    /// Physical values by column, None for signals not in the message with `id`
+    pub fn decode(&self, id: u32, data: &[u8]) -> Vec<Option<f64>> {
+        let mut values = vec![None; self.columns.len()];
+        let Some(specs) = self.messages.get(&id) else {
+            return values;
+        };
+
+        let mux = specs.iter()
+                       .find(|spec| spec.is_multiplexor)
+                       .and_then(|spec| raw_value(spec, data));
+        for spec in specs {
+            if spec.mux_value.is_some() && spec.mux_value != mux {
+                continue;
+            }
+            values[spec.column] = raw_value(spec, data).map(|raw| physical_value(spec, raw));
+        }
+        values
+    }
+}
+
+fn bit(data: &[u8], position: u64) -> Option<u64> {
+    let byte = data.get((position / 8) as usize)?;
+    Some(u64::from((byte >> (position % 8)) & 1))
+}
+
+/// Unsigned raw value, None when the signal does not fit in `data`
+fn raw_value(spec: &SignalSpec, data: &[u8]) -> Option<u64> {
+    if spec.size == 0 || spec.size > 64 {
+        return None;
+    }
+    let mut raw = 0u64;
+    if spec.little_endian {
+        for i in 0..spec.size {
+            raw |= bit(data, spec.start_bit + i)? << i;
+        }
+    } else {
+        // Motorola: start bit is the MSB, bits go down inside a byte then jump to the next byte
+        let mut position = spec.start_bit;
+        for _ in 0..spec.size {
+            raw = (raw << 1) | bit(data, position)?;
+            position = if position.is_multiple_of(8) { position + 15 } else { position - 1 };
+        }
+    }
+    Some(raw)
+}
+
+fn physical_value(spec: &SignalSpec, raw: u64) -> f64 {
+    let value = if spec.signed && spec.size < 64 && (raw >> (spec.size - 1)) & 1 == 1 {
+        (raw | (u64::MAX << spec.size)) as i64 as f64
+    } else if spec.signed {
+        raw as i64 as f64
+    } else {
+        raw as f64
+    };
+    value * spec.factor + spec.offset
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use arrow::datatypes::{DataType, Field, Schema};
+    use super::*;
+    use crate::parquet_writer::with_signal_fields;
+
+    const DBC_FILE: &str = r#"VERSION ""
+
+NS_ :
+
+BS_:
+
+BU_: ECU
+
+BO_ 256 Engine: 8 ECU
+ SG_ Speed : 0|16@1+ (0.1,-10) [0|0] "km/h" ECU
+ SG_ Temp : 16|8@1- (1,0) [0|0] "C" ECU
+ SG_ Torque : 39|12@0- (0.5,0) [0|0] "Nm" ECU
+ SG_ Load : 55|8@0+ (1,0) [0|0] "%" ECU
+
+BO_ 512 Diag: 8 ECU
+ SG_ Mux M : 0|8@1+ (1,0) [0|0] "" ECU
+ SG_ A m1 : 8|8@1+ (1,0) [0|0] "" ECU
+ SG_ B m2 : 8|8@1+ (1,0) [0|0] "" ECU
+ SG_ id : 16|8@1+ (1,0) [0|0] "" ECU
+"#;
+
+    fn dbc() -> Dbc {
+        Dbc::from_slice(DBC_FILE.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn intel_and_motorola_signals() {
+        let dbc = dbc();
+        assert_eq!(dbc.columns(), ["Speed", "Temp", "Torque", "Load", "Mux", "A", "B", "id"]);
+        // Speed 1000 * 0.1 - 10, Temp -10, Torque 0xF9C (-100) * 0.5 in byte 4 and the high nibble of byte 5,
+        // Load 0x42 in byte 6
+        let values = dbc.decode(256, &[0xE8, 0x03, 0xF6, 0x00, 0xF9, 0xC0, 0x42, 0x00]);
+        assert_eq!(values[..4], [Some(90.0), Some(-10.0), Some(-50.0), Some(66.0)]);
+        assert_eq!(values[4..], [None; 4]);
+    }
+
+    #[test]
+    fn signals_past_the_data_are_null() {
+        let values = dbc().decode(256, &[0xE8, 0x03, 0x0A]);
+        assert_eq!(values[..4], [Some(90.0), Some(10.0), None, None]);
+        assert_eq!(dbc().decode(0x300, &[0; 8]), [None; 8]);
+    }
+
+    #[test]
+    fn multiplexed_signals_follow_the_multiplexor() {
+        assert_eq!(dbc().decode(512, &[1, 42, 7])[4..], [Some(1.0), Some(42.0), None, Some(7.0)]);
+        assert_eq!(dbc().decode(512, &[2, 42, 7])[4..], [Some(2.0), None, Some(42.0), Some(7.0)]);
+    }
+
+    #[test]
+    fn signal_named_like_a_frame_column_is_prefixed() {
+        let schema = Arc::new(Schema::new(vec![Field::new("id", DataType::UInt32, false)]));
+        let schema = with_signal_fields(schema, dbc().columns());
+        let names: Vec<&str> = schema.fields().iter().map(|field| field.name().as_str()).collect();
+        assert_eq!(names, ["id", "Speed", "Temp", "Torque", "Load", "Mux", "A", "B", "signal_id"]);
+    }
+}
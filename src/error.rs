@@ -0,0 +1,51 @@
+//! Errors of the conversion and the replay
+
+use std::{io, path::PathBuf};
+use arrow::error::ArrowError;
+use parquet::errors::ParquetError;
+use crate::sink::SendError;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    /// Input or output file that cannot be opened or created
+    #[error("failed to open {}: {source}", path.display())]
+    File { path: PathBuf, source: io::Error },
+    #[error("arrow error: {0}")]
+    Arrow(#[from] ArrowError),
+    #[error("parquet error: {0}")]
+    Parquet(#[from] ParquetError),
+    /// Parquet footer or schema that cannot be read
+    #[error("failed to open parquet: {0}")]
+    OpenParquet(ParquetError),
+    #[error("invalid BLF: {0}")]
+    Blf(String),
+    #[error("invalid DBC: {0}")]
+    Dbc(String),
+    /// The frame cannot be built from the row
+    #[error("failed to build CAN frame: {0}")]
+    Can(String),
+    /// The bus refused the frame
+    #[error("failed to send CAN frame: {0}")]
+    Bus(String),
+    /// Option values the conversion or the replay cannot run with
+    #[error("{0}")]
+    Options(String),
+}
+
+impl From<SendError> for Error {
+    fn from(error: SendError) -> Self {
+        match error {
+            SendError::Frame(message) => Error::Can(message),
+            SendError::Bus(message) | SendError::Busy(message) => Error::Bus(message),
+        }
+    }
+}
+
+impl Error {
+    /// `io::Error` of `path`, keeping the path in the message
+    pub fn file(path: impl Into<PathBuf>, source: io::Error) -> Self {
+        Error::File { path: path.into(), source }
+    }
+}
@@ -0,0 +1,118 @@
+//! CAN ID filters shared by the conversion and the replay
+
+use std::{fs, path::Path};
+use crate::Error;
+
+/// Hex (0x-prefixed) or decimal ID
+pub fn parse_id(input: &str) -> Result<u32, String> {
+    let input = input.trim();
+    let id = match input.strip_prefix("0x").or_else(|| input.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => input.parse::<u32>(),
+    };
+    id.map_err(|error| format!("invalid ID {:?}: {}", input, error))
+}
+
+/// One hex or decimal ID per line, `#` starts a comment, the error names the line
+pub fn parse_id_list(input: &str) -> Result<Vec<u32>, String> {
+    let mut ids = Vec::new();
+    for (index, line) in input.lines().enumerate() {
+        let line = line.split_once('#').map_or(line, |(id, _)| id).trim();
+        if !line.is_empty() {
+            ids.push(parse_id(line).map_err(|error| format!("line {}: {}", index + 1, error))?);
+        }
+    }
+    Ok(ids)
+}
+
+/// IDs of a `parse_id_list` file
+pub fn read_id_file(path: &Path) -> Result<Vec<u32>, Error> {
+    let input = fs::read_to_string(path).map_err(|error| Error::file(path, error))?;
+    parse_id_list(&input).map_err(|error| Error::Options(format!("{}: {}", path.display(), error)))
+}
+
+/// ID list file arguments of the conversion and the replay
+#[derive(Debug, clap::Args)]
+pub struct IdFileArgs {
+    /// File with one ID per line, hex or decimal, `#` comments, added to --include-id
+    #[arg(long)]
+    pub id_filter_file: Option<String>,
+
+    /// Add the IDs of --id-filter-file to --exclude-id instead
+    #[arg(long, default_value_t = false, requires = "id_filter_file")]
+    pub invert_filter: bool,
+}
+
+impl IdFileArgs {
+    /// Adds the IDs of the file to `include_id`, or to `exclude_id` with `invert_filter`
+    pub fn extend(&self, include_id: &mut Vec<u32>, exclude_id: &mut Vec<u32>) -> Result<(), Error> {
+        if let Some(path) = &self.id_filter_file {
+            let ids = read_id_file(Path::new(path))?;
+            if self.invert_filter { exclude_id.extend(ids) } else { include_id.extend(ids) }
+        }
+        Ok(())
+    }
+}
+
+/// `original:new` pair of IDs (eg: "0x100:0x200")
+pub fn parse_id_pair(input: &str) -> Result<(u32, u32), String> {
+    let (original, new) = input.split_once(':')
+                               .ok_or_else(|| format!("invalid ID pair {:?}, expected original:new", input.trim()))?;
+    Ok((parse_id(original)?, parse_id(new)?))
+}
+
+/// `id & mask == value` for one of the `(mask, value)` pairs
+pub fn id_masked(id: u32, masks: &[(u32, u32)]) -> bool {
+    masks.iter().any(|&(mask, value)| id & mask == value)
+}
+
+/// Include list first (empty means every ID), then exclude list: an ID in both is dropped
+pub fn id_allowed(id: u32, include_id: &[u32], exclude_id: &[u32]) -> bool {
+    (include_id.is_empty() || include_id.contains(&id)) && !exclude_id.contains(&id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_hex_and_decimal_ids() {
+        assert_eq!(parse_id("0x1FF"), Ok(0x1FF));
+        assert_eq!(parse_id(" 0X1ff "), Ok(0x1FF));
+        assert_eq!(parse_id("511"), Ok(0x1FF));
+        assert!(parse_id("0xZZ").unwrap_err().starts_with("invalid ID \"0xZZ\""));
+        assert!(parse_id("-1").is_err());
+    }
+
+    #[test]
+    fn id_list_skips_comments_and_names_the_line() {
+        assert_eq!(parse_id_list("0x100 # engine\n\n# comment\n512\n"), Ok(vec![0x100, 512]));
+        assert!(parse_id_list("0x100\nabc\n").unwrap_err().starts_with("line 2: "));
+    }
+
+    #[test]
+    fn id_pair() {
+        assert_eq!(parse_id_pair("0x100:0x200"), Ok((0x100, 0x200)));
+        assert_eq!(parse_id_pair("256 : 512"), Ok((256, 512)));
+        assert!(parse_id_pair("0x100").unwrap_err().contains("expected original:new"));
+    }
+
+    #[test]
+    fn masked_ids() {
+        let masks = [(0x700, 0x100), (0x7FF, 0x7DF)];
+        assert!(id_masked(0x1AB, &masks));
+        assert!(id_masked(0x7DF, &masks));
+        assert!(!id_masked(0x2AB, &masks));
+        assert!(!id_masked(0x1AB, &[]));
+    }
+
+    #[test]
+    fn exclude_wins_over_include() {
+        assert!(id_allowed(0x100, &[0x100, 0x200], &[]));
+        assert!(!id_allowed(0x100, &[0x100, 0x200], &[0x100]));
+        assert!(id_allowed(0x200, &[0x100, 0x200], &[0x100]));
+        // empty include list: every ID but the excluded ones
+        assert!(!id_allowed(0x100, &[], &[0x100]));
+        assert!(id_allowed(0x300, &[], &[0x100]));
+    }
+}
@@ -0,0 +1,155 @@
+//! ISO-TP (ISO 15765-2) reassembly of the payloads segmented over CAN frames
+
+use std::collections::HashMap;
+
+// PCI type, high nibble of the first data byte
+const PCI_SINGLE_FRAME: u8 = 0x0;
+const PCI_FIRST_FRAME: u8 = 0x1;
+const PCI_CONSECUTIVE_FRAME: u8 = 0x2;
+const PCI_FLOW_CONTROL: u8 = 0x3;
+
+/// Payload of a single frame, or of a first frame and its consecutive frames
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IsoTpMessage {
+    /// First segment, nanoseconds since Unix epoch
+    pub ts_start: i64,
+    /// Last segment, equal to `ts_start` for single frames
+    pub ts_end: i64,
+    pub channel: u16,
+    pub id: u32,
+    pub payload: Vec<u8>,
+}
+
+/// First frame waiting for its consecutive frames
+#[derive(Debug)]
+struct Pending {
+    message: IsoTpMessage,
+    /// Length announced by the first frame
+    length: usize,
+    /// Sequence number expected in the next consecutive frame, 0-15
+    next_sn: u8,
+}
+
+/// Reassembles the frames of each channel and ID, which must be pushed in timestamp order
+#[derive(Debug, Default)]
+pub struct Reassembler {
+    pending: HashMap<(u16, u32), Pending>,
+    /// Messages abandoned on a sequence error or a new first frame, and consecutive frames without first frame
+    pub dropped: usize,
+}
+
+impl Reassembler {
+    /// Adds a frame, returns the message it completes. Frames that are not ISO-TP segments are ignored.
+    pub fn push(&mut self, ts: i64, channel: u16, id: u32, data: &[u8]) -> Option<IsoTpMessage> {
+        let pci = *data.first()?;
+        let key = (channel, id);
+        let message = |payload: &[u8]| {
+            IsoTpMessage { ts_start: ts, ts_end: ts, channel, id, payload: payload.to_vec() }
+        };
+
+        match pci >> 4 {
+            PCI_SINGLE_FRAME => {
+                self.abandon(key);
+                // CAN FD single frames move the length to the second byte
+                let (length, payload) = match pci & 0x0F {
+                    0 => (usize::from(*data.get(1)?), data.get(2..)?),
+                    length => (usize::from(length), &data[1..]),
+                };
+                Some(message(payload.get(..length)?))
+            }
+            PCI_FIRST_FRAME => {
+                self.abandon(key);
+                let length = (usize::from(pci & 0x0F) << 8) | usize::from(*data.get(1)?);
+                // lengths above 4095 are escaped to 32 bits
+                let (length, payload) = if length == 0 {
+                    let escaped: [u8; 4] = data.get(2..6)?.try_into().ok()?;
+                    (u32::from_be_bytes(escaped) as usize, data.get(6..)?)
+                } else {
+                    (length, &data[2..])
+                };
+                let mut first = message(payload);
+                if first.payload.len() >= length {
+                    first.payload.truncate(length);
+                    return Some(first);
+                }
+                self.pending.insert(key, Pending { message: first, length, next_sn: 1 });
+                None
+            }
+            PCI_CONSECUTIVE_FRAME => {
+                let Some(pending) = self.pending.get_mut(&key) else {
+                    self.dropped += 1;
+                    return None;
+                };
+                if pci & 0x0F != pending.next_sn {
+                    self.abandon(key);
+                    return None;
+                }
+                pending.next_sn = (pending.next_sn + 1) & 0x0F;
+                pending.message.ts_end = ts;
+                // the last consecutive frame is padded
+                let missing = pending.length - pending.message.payload.len();
+                let payload = &data[1..];
+                pending.message.payload.extend_from_slice(&payload[..payload.len().min(missing)]);
+                if pending.message.payload.len() < pending.length {
+                    return None;
+                }
+                self.pending.remove(&key).map(|pending| pending.message)
+            }
+            // sent by the receiver, they carry no payload
+            PCI_FLOW_CONTROL => None,
+            _ => None,
+        }
+    }
+
+    fn abandon(&mut self, key: (u16, u32)) {
+        if self.pending.remove(&key).is_some() {
+            self.dropped += 1;
+        }
+    }
+
+    /// First frames still waiting for consecutive frames
+    pub fn incomplete(&self) -> usize {
+        self.pending.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_frame() {
+        let mut reassembler = Reassembler::default();
+        let message = reassembler.push(10, 1, 0x7E8, &[0x03, 0x41, 0x0D, 0x32, 0xAA, 0xAA, 0xAA, 0xAA]).unwrap();
+        assert_eq!(message, IsoTpMessage { ts_start: 10, ts_end: 10, channel: 1, id: 0x7E8,
+                                           payload: vec![0x41, 0x0D, 0x32] });
+        // CAN FD escape: the length is in the second byte
+        let message = reassembler.push(11, 1, 0x7E8, &[0x00, 0x02, 0x50, 0x01, 0xCC]).unwrap();
+        assert_eq!(message.payload, [0x50, 0x01]);
+    }
+
+    #[test]
+    fn first_and_consecutive_frames() {
+        let mut reassembler = Reassembler::default();
+        // 14 bytes: 6 in the first frame, 7 then 1 (padded) in the consecutive frames
+        assert_eq!(reassembler.push(10, 1, 0x7E8, &[0x10, 0x0E, 0, 1, 2, 3, 4, 5]), None);
+        // flow control from the tester and frames of another ID are not part of the message
+        assert_eq!(reassembler.push(11, 1, 0x7E0, &[0x30, 0x00, 0x00, 0, 0, 0, 0, 0]), None);
+        assert_eq!(reassembler.push(12, 1, 0x7E8, &[0x21, 6, 7, 8, 9, 10, 11, 12]), None);
+        assert_eq!(reassembler.incomplete(), 1);
+        let message = reassembler.push(13, 1, 0x7E8, &[0x22, 13, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA]).unwrap();
+        assert_eq!((message.ts_start, message.ts_end), (10, 13));
+        assert_eq!(message.payload, (0..14).collect::<Vec<u8>>());
+        assert_eq!((reassembler.incomplete(), reassembler.dropped), (0, 0));
+    }
+
+    #[test]
+    fn sequence_error_drops_the_message() {
+        let mut reassembler = Reassembler::default();
+        assert_eq!(reassembler.push(10, 1, 0x7E8, &[0x10, 0x0E, 0, 1, 2, 3, 4, 5]), None);
+        assert_eq!(reassembler.push(12, 1, 0x7E8, &[0x22, 6, 7, 8, 9, 10, 11, 12]), None);
+        // the consecutive frame of the abandoned message has no first frame anymore
+        assert_eq!(reassembler.push(13, 1, 0x7E8, &[0x23, 13, 0, 0, 0, 0, 0, 0]), None);
+        assert_eq!((reassembler.incomplete(), reassembler.dropped), (0, 2));
+    }
+}
@@ -0,0 +1,79 @@
+//! J1939 fields of extended CAN IDs, shared by the PGN filter of the conversion
+
+use crate::ids::parse_id;
+
+/// Highest PDU format of the PDU1 groups, whose PDU specific byte is a destination address
+const PDU1_MAX_FORMAT: u32 = 239;
+
+/// Priority, parameter group number and addresses of a 29-bit ID
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct J1939Id {
+    /// 0 is the highest priority, 7 the lowest
+    pub priority: u8,
+    /// 18-bit PGN: extended data page, data page, PDU format and, for PDU2 groups, PDU specific
+    pub pgn: u32,
+    /// Destination address of PDU1 groups, None for the PDU2 broadcasts
+    pub destination: Option<u8>,
+    pub source: u8,
+}
+
+impl J1939Id {
+    /// Splits a 29-bit ID, the caller checks that the frame is extended
+    pub fn from_id(id: u32) -> Self {
+        let pgn = (id >> 8) & 0x3FFFF;
+        let pdu_format = (pgn >> 8) & 0xFF;
+        let (pgn, destination) = if pdu_format <= PDU1_MAX_FORMAT {
+            (pgn & !0xFF, Some(pgn as u8))
+        } else {
+            (pgn, None)
+        };
+        J1939Id { priority: ((id >> 26) & 0x7) as u8, pgn, destination, source: id as u8 }
+    }
+}
+
+/// Hex (0x-prefixed) or decimal PGN, a PDU1 PGN must leave its destination byte at 0 (eg: "0xEF00")
+pub fn parse_pgn(input: &str) -> Result<u32, String> {
+    let pgn = parse_id(input).map_err(|_| format!("invalid PGN {:?}", input.trim()))?;
+    if pgn > 0x3FFFF {
+        return Err(format!("PGN {:#X} does not fit in 18 bits", pgn));
+    }
+    if (pgn >> 8) & 0xFF <= PDU1_MAX_FORMAT && pgn & 0xFF != 0 {
+        return Err(format!("PGN {:#X} is peer to peer (PDU1), its low byte is the destination and must be 0 \
+                            (eg: {:#X})", pgn, pgn & !0xFF));
+    }
+    Ok(pgn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pdu1_id_has_a_destination() {
+        // priority 3, TSC1 (PGN 0), destination 0x00, source 0x03
+        assert_eq!(J1939Id::from_id(0x0C00_0003),
+                   J1939Id { priority: 3, pgn: 0, destination: Some(0x00), source: 0x03 });
+        // priority 6, PGN 0xEA00 (request) to 0x25 from 0xF9
+        assert_eq!(J1939Id::from_id(0x18EA_25F9),
+                   J1939Id { priority: 6, pgn: 0xEA00, destination: Some(0x25), source: 0xF9 });
+    }
+
+    #[test]
+    fn pdu2_id_is_a_broadcast() {
+        // priority 3, EEC1 (PGN 0xF004) from 0x00
+        assert_eq!(J1939Id::from_id(0x0CF0_0400),
+                   J1939Id { priority: 3, pgn: 0xF004, destination: None, source: 0x00 });
+        // data page set
+        assert_eq!(J1939Id::from_id(0x19FE_CA11).pgn, 0x1FECA);
+    }
+
+    #[test]
+    fn parse_pgn_checks_the_range_and_pdu1_low_byte() {
+        assert_eq!(parse_pgn("0xF004"), Ok(0xF004));
+        assert_eq!(parse_pgn("0xEF00"), Ok(0xEF00));
+        assert_eq!(parse_pgn("61444"), Ok(0xF004));
+        assert!(parse_pgn("0x40000").unwrap_err().contains("18 bits"));
+        assert!(parse_pgn("0xEF25").unwrap_err().contains("0xEF00"));
+        assert!(parse_pgn("EEC1").unwrap_err().starts_with("invalid PGN"));
+    }
+}
@@ -0,0 +1,20 @@
+pub mod blf;
+pub mod blf_writer;
+pub mod convert;
+pub mod dbc;
+pub mod error;
+pub mod ids;
+pub mod isotp;
+pub mod j1939;
+pub mod parquet_writer;
+pub mod progress;
+pub mod replay;
+pub mod sink;
+
+pub use convert::{
+    ConvertOptions, ConvertSummary, DlcMismatch, FileSummary, Format, Monotonic, PercentOf, Protocol, blf_to_parquet,
+    blfs_to_parquet, blfs_to_parquet_by_channel, count_matched,
+};
+pub use error::Error;
+pub use replay::{ReplayFrame, ReplayOptions, replay_parquet};
+pub use sink::{Backend, CanSink};
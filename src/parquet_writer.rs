@@ -0,0 +1,944 @@
+//! Arrow schema and record batches of the converted frames
+
+use std::{fs::File, io::{self, Write}, sync::Arc};
+use clap::ValueEnum;
+use arrow::{
+    buffer::OffsetBuffer,
+    compute::{sort_to_indices, take_record_batch},
+    array::{
+        ArrayRef, BinaryArray, BooleanArray, StringArray, UInt8Array, UInt16Array, UInt32Array, Int64Array,
+        Float64Array, LargeListArray, TimestampNanosecondArray,
+    },
+    record_batch::RecordBatch,
+    datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit},
+    error::ArrowError,
+};
+use parquet::{
+    arrow::ArrowWriter,
+    basic::{BrotliLevel, Compression, GzipLevel, ZstdLevel},
+    errors::ParquetError,
+    file::properties::WriterProperties,
+    schema::types::ColumnPath,
+};
+use crate::{
+    blf::{
+        BusStatistic, ErrorFrame, EthernetFrame, Event, FlexRayFrame, Frame, LinFrame, flexray_channel_name,
+        format_mac,
+    },
+    isotp::IsoTpMessage,
+};
+
+/// Column buffers flushed to the writer as record batches
+pub trait FrameBuffer {
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn clear(&mut self);
+
+    fn build(&self, schema: &SchemaRef) -> Result<RecordBatch, ArrowError>;
+
+    /// Writes the rows as `ts,id,data_hex` lines, only CAN and LIN frames have a CSV form
+    fn write_csv(&self, _schema: &SchemaRef, _writer: &mut dyn Write) -> io::Result<()> {
+        Err(io::Error::other("CSV output only holds CAN and LIN frames"))
+    }
+}
+
+/// Type of the ts column, the time since Unix epoch
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum TsType {
+    /// Int64 nanoseconds
+    #[default]
+    Int64,
+    /// Float64 seconds, for legacy readers
+    Float64,
+    /// Timestamp(Nanosecond, "UTC"), a datetime for query engines
+    Timestamp,
+}
+
+impl TsType {
+    /// Type of the ts column of `schema`, its first field
+    pub fn of(schema: &Schema) -> Self {
+        match schema.field(0).data_type() {
+            DataType::Float64 => TsType::Float64,
+            DataType::Timestamp(..) => TsType::Timestamp,
+            _ => TsType::Int64,
+        }
+    }
+}
+
+pub fn ts_field(ts_type: TsType) -> Field {
+    match ts_type {
+        TsType::Int64 => Field::new("ts", DataType::Int64, false),
+        TsType::Float64 => Field::new("ts", DataType::Float64, false),
+        TsType::Timestamp => Field::new("ts", DataType::Timestamp(TimeUnit::Nanosecond, Some("UTC".into())), false),
+    }
+}
+
+pub fn ts_array(ts: &[i64], ts_type: TsType) -> ArrayRef {
+    match ts_type {
+        TsType::Int64 => Arc::new(Int64Array::from_iter_values(ts.iter().copied())),
+        TsType::Float64 => Arc::new(Float64Array::from_iter_values(ts.iter().map(|ns| *ns as f64 / 1e9))),
+        TsType::Timestamp => {
+            Arc::new(TimestampNanosecondArray::from_iter_values(ts.iter().copied()).with_timezone("UTC"))
+        }
+    }
+}
+
+/// Optional columns written after ts, id, data, dlc and channel
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ExtraColumns {
+    /// CAN remote frame flag
+    pub rtr: bool,
+    /// CAN extended (29-bit) identifier flag
+    pub ext: bool,
+    /// is_fd, brs and esi
+    pub fd: bool,
+    /// Rx/Tx direction
+    pub dir: bool,
+    /// is_error and error_code, for CAN error frames written as rows
+    pub errors: bool,
+}
+
+pub fn frame_schema(extra: ExtraColumns, ts_type: TsType) -> SchemaRef {
+    let mut fields = vec![
+        ts_field(ts_type),
+        Field::new("id", DataType::UInt32, false),
+        Field::new("data", DataType::LargeList(Arc::new(Field::new_list_field(DataType::UInt8, true))), false),
+        Field::new("dlc", DataType::UInt8, false),
+        Field::new("channel", DataType::UInt16, false),
+    ];
+    if extra.rtr {
+        fields.push(Field::new("rtr", DataType::Boolean, false));
+    }
+    if extra.ext {
+        fields.push(Field::new("extended", DataType::Boolean, false));
+    }
+    if extra.fd {
+        fields.push(Field::new("is_fd", DataType::Boolean, false));
+        fields.push(Field::new("brs", DataType::Boolean, false));
+        fields.push(Field::new("esi", DataType::Boolean, false));
+    }
+    if extra.dir {
+        fields.push(Field::new("dir", DataType::UInt8, false));
+    }
+    if extra.errors {
+        fields.push(Field::new("is_error", DataType::Boolean, false));
+        fields.push(Field::new("error_code", DataType::UInt8, true));
+    }
+    Arc::new(Schema::new(fields))
+}
+
+/// Appends a nullable Float64 column per decoded signal, "signal_" is prepended to the names of frame columns
+pub fn with_signal_fields(schema: SchemaRef, signals: &[String]) -> SchemaRef {
+    let mut fields: Vec<Field> = schema.fields().iter().map(|field| field.as_ref().clone()).collect();
+    let names = signals.iter().map(|name| match schema.column_with_name(name) {
+        Some(_) => format!("signal_{}", name),
+        None => name.clone(),
+    });
+    fields.extend(names.map(|name| Field::new(name, DataType::Float64, true)));
+    Arc::new(Schema::new(fields))
+}
+
+pub fn flexray_schema(ts_type: TsType) -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        ts_field(ts_type),
+        Field::new("slot", DataType::UInt16, false),
+        Field::new("cycle", DataType::UInt8, false),
+        Field::new("channel", DataType::Utf8, false),
+        Field::new("payload", DataType::LargeList(Arc::new(Field::new_list_field(DataType::UInt8, true))), false),
+    ]))
+}
+
+pub fn ethernet_schema(ts_type: TsType) -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        ts_field(ts_type),
+        Field::new("channel", DataType::UInt16, false),
+        Field::new("source", DataType::Utf8, false),
+        Field::new("destination", DataType::Utf8, false),
+        Field::new("ethertype", DataType::UInt16, false),
+        Field::new("payload", DataType::Binary, false),
+    ]))
+}
+
+pub fn lin_schema(ts_type: TsType) -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        ts_field(ts_type),
+        Field::new("channel", DataType::UInt16, false),
+        Field::new("id", DataType::UInt8, false),
+        Field::new("data", DataType::LargeList(Arc::new(Field::new_list_field(DataType::UInt8, true))), false),
+        Field::new("checksum", DataType::UInt8, false),
+    ]))
+}
+
+/// Messages reassembled by istp-reassemble, ts_start and ts_end are Int64 nanoseconds
+pub fn isotp_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new("ts_start", DataType::Int64, false),
+        Field::new("ts_end", DataType::Int64, false),
+        Field::new("channel", DataType::UInt16, false),
+        Field::new("id", DataType::UInt32, false),
+        Field::new("payload", DataType::Binary, false),
+        Field::new("length", DataType::UInt32, false),
+    ]))
+}
+
+pub fn error_schema(ts_type: TsType) -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        ts_field(ts_type),
+        Field::new("channel", DataType::UInt16, false),
+        Field::new("error_code", DataType::UInt8, true),
+    ]))
+}
+
+pub fn statistic_schema(ts_type: TsType) -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        ts_field(ts_type),
+        Field::new("channel", DataType::UInt16, false),
+        Field::new("bus_load", DataType::Float64, false),
+        Field::new("std_frames", DataType::UInt32, false),
+        Field::new("ext_frames", DataType::UInt32, false),
+        Field::new("error_frames", DataType::UInt32, false),
+    ]))
+}
+
+pub fn event_schema(ts_type: TsType) -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        ts_field(ts_type),
+        Field::new("text", DataType::Utf8, false),
+    ]))
+}
+
+/// Frame columns accumulated between two batch flushes
+#[derive(Debug, Default)]
+pub struct Frames {
+    pub ts: Vec<i64>,
+    pub id: Vec<u32>,
+    pub data: Vec<u8>,
+    pub len: Vec<usize>,
+    pub dlc: Vec<u8>,
+    /// BLF channel minus 1, so --channel 1 is stored as 0
+    pub channel: Vec<u16>,
+    pub rtr: Vec<bool>,
+    pub ext: Vec<bool>,
+    pub fd: Vec<bool>,
+    pub brs: Vec<bool>,
+    pub esi: Vec<bool>,
+    pub dir: Vec<u8>,
+    pub is_error: Vec<bool>,
+    pub error_code: Vec<Option<u8>>,
+    /// Decoded DBC signals, one vector per signal column
+    pub signals: Vec<Vec<Option<f64>>>,
+}
+
+impl Frames {
+    pub fn with_signals(columns: usize) -> Self {
+        Frames {
+            signals: vec![Vec::new(); columns],
+            ..Default::default()
+        }
+    }
+
+    /// Must follow `push` when the frames have signal columns
+    pub fn push_signals(&mut self, values: Vec<Option<f64>>) {
+        for (column, value) in self.signals.iter_mut().zip(values) {
+            column.push(value);
+        }
+    }
+
+    pub fn push(&mut self, frame: &Frame) {
+        self.ts.push(frame.ts);
+        self.id.push(frame.id);
+        self.data.extend_from_slice(frame.data);
+        self.len.push(frame.data.len());
+        self.dlc.push(frame.dlc);
+        self.channel.push(frame.channel.saturating_sub(1));
+        self.rtr.push(frame.rtr);
+        self.ext.push(frame.ext);
+        self.fd.push(frame.fd);
+        self.brs.push(frame.brs);
+        self.esi.push(frame.esi);
+        self.dir.push(frame.dir);
+        self.is_error.push(false);
+        self.error_code.push(None);
+    }
+
+    /// Error frame row, with the ID and data of the frame the controller recorded
+    pub fn push_error(&mut self, error: &ErrorFrame) {
+        self.push(&Frame {
+            ts: error.ts,
+            channel: error.channel,
+            id: error.id,
+            ext: error.ext,
+            data: &error.data[..usize::from(error.dlc).min(8)],
+            dlc: error.dlc,
+            rtr: false,
+            fd: false,
+            brs: false,
+            esi: false,
+            dir: 0,
+        });
+        if let (Some(is_error), Some(error_code)) = (self.is_error.last_mut(), self.error_code.last_mut()) {
+            *is_error = true;
+            *error_code = error.error_code;
+        }
+    }
+
+    /// Moves the rows of `other` after these ones, leaving it empty
+    pub fn append(&mut self, other: &mut Frames) {
+        self.ts.append(&mut other.ts);
+        self.id.append(&mut other.id);
+        self.data.append(&mut other.data);
+        self.len.append(&mut other.len);
+        self.dlc.append(&mut other.dlc);
+        self.channel.append(&mut other.channel);
+        self.rtr.append(&mut other.rtr);
+        self.ext.append(&mut other.ext);
+        self.fd.append(&mut other.fd);
+        self.brs.append(&mut other.brs);
+        self.esi.append(&mut other.esi);
+        self.dir.append(&mut other.dir);
+        self.is_error.append(&mut other.is_error);
+        self.error_code.append(&mut other.error_code);
+        for (column, values) in self.signals.iter_mut().zip(other.signals.iter_mut()) {
+            column.append(values);
+        }
+    }
+
+    /// Keeps the frames whose `keep` entry is true
+    pub fn retain(&mut self, keep: &[bool]) {
+        let mut data = Vec::with_capacity(self.data.len());
+        let mut offset = 0;
+        for (len, kept) in self.len.iter().zip(keep) {
+            if *kept {
+                data.extend_from_slice(&self.data[offset..offset + len]);
+            }
+            offset += len;
+        }
+        self.data = data;
+        retain_rows(&mut self.ts, keep);
+        retain_rows(&mut self.id, keep);
+        retain_rows(&mut self.len, keep);
+        retain_rows(&mut self.dlc, keep);
+        retain_rows(&mut self.channel, keep);
+        retain_rows(&mut self.rtr, keep);
+        retain_rows(&mut self.ext, keep);
+        retain_rows(&mut self.fd, keep);
+        retain_rows(&mut self.brs, keep);
+        retain_rows(&mut self.esi, keep);
+        retain_rows(&mut self.dir, keep);
+        retain_rows(&mut self.is_error, keep);
+        retain_rows(&mut self.error_code, keep);
+        self.signals.iter_mut().for_each(|column| retain_rows(column, keep));
+    }
+
+    /// Keeps the first `len` frames
+    pub fn truncate(&mut self, len: usize) {
+        if len >= self.ts.len() {
+            return;
+        }
+        self.data.truncate(self.len[..len].iter().sum());
+        self.ts.truncate(len);
+        self.id.truncate(len);
+        self.len.truncate(len);
+        self.dlc.truncate(len);
+        self.channel.truncate(len);
+        self.rtr.truncate(len);
+        self.ext.truncate(len);
+        self.fd.truncate(len);
+        self.brs.truncate(len);
+        self.esi.truncate(len);
+        self.dir.truncate(len);
+        self.is_error.truncate(len);
+        self.error_code.truncate(len);
+        self.signals.iter_mut().for_each(|column| column.truncate(len));
+    }
+}
+
+fn retain_rows<T>(values: &mut Vec<T>, keep: &[bool]) {
+    let mut rows = keep.iter();
+    values.retain(|_| rows.next().copied().unwrap_or(false));
+}
+
+impl FrameBuffer for Frames {
+    fn len(&self) -> usize {
+        self.ts.len()
+    }
+
+    fn clear(&mut self) {
+        self.ts.clear();
+        self.id.clear();
+        self.data.clear();
+        self.len.clear();
+        self.dlc.clear();
+        self.channel.clear();
+        self.rtr.clear();
+        self.ext.clear();
+        self.fd.clear();
+        self.brs.clear();
+        self.esi.clear();
+        self.dir.clear();
+        self.is_error.clear();
+        self.error_code.clear();
+        self.signals.iter_mut().for_each(Vec::clear);
+    }
+
+    fn build(&self, schema: &SchemaRef) -> Result<RecordBatch, ArrowError> {
+        build_record_batch(schema, self)
+    }
+
+    /// ts as in the parquet output, id zero-padded to 3 or 8 hex digits (extended), data as contiguous hex bytes
+    fn write_csv(&self, schema: &SchemaRef, writer: &mut dyn Write) -> io::Result<()> {
+        let ts_type = TsType::of(schema);
+        let mut offsets = Vec::with_capacity(self.len.len());
+        let mut offset = 0;
+        for len in &self.len {
+            offsets.push(offset);
+            offset += len;
+        }
+        // same order as the parquet batches, the sort is stable
+        let mut rows: Vec<usize> = (0..self.ts.len()).collect();
+        rows.sort_by_key(|row| self.ts[*row]);
+
+        for row in rows {
+            let ts = self.ts[row];
+            if ts_type == TsType::Float64 {
+                write!(writer, "{}.{:09}", ts.div_euclid(1_000_000_000), ts.rem_euclid(1_000_000_000))?;
+            } else {
+                write!(writer, "{}", ts)?;
+            }
+            let width = if self.ext[row] { 8 } else { 3 };
+            write!(writer, ",{:0width$X},", self.id[row], width = width)?;
+            for byte in &self.data[offsets[row]..offsets[row] + self.len[row]] {
+                write!(writer, "{:02X}", byte)?;
+            }
+            writeln!(writer)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct FlexRayFrames {
+    pub ts: Vec<i64>,
+    pub slot: Vec<u16>,
+    pub cycle: Vec<u8>,
+    pub channel: Vec<&'static str>,
+    pub payload: Vec<u8>,
+    pub len: Vec<usize>,
+}
+
+impl FlexRayFrames {
+    /// Frames without a valid channel A/B are not pushed
+    pub fn push(&mut self, frame: &FlexRayFrame) -> bool {
+        let Some(channel) = flexray_channel_name(frame.channel_mask) else {
+            return false;
+        };
+        self.ts.push(frame.ts);
+        self.slot.push(frame.slot);
+        self.cycle.push(frame.cycle);
+        self.channel.push(channel);
+        self.payload.extend_from_slice(frame.payload);
+        self.len.push(frame.payload.len());
+        true
+    }
+
+    pub fn append(&mut self, other: &mut FlexRayFrames) {
+        self.ts.append(&mut other.ts);
+        self.slot.append(&mut other.slot);
+        self.cycle.append(&mut other.cycle);
+        self.channel.append(&mut other.channel);
+        self.payload.append(&mut other.payload);
+        self.len.append(&mut other.len);
+    }
+
+    /// Keeps the first `len` frames
+    pub fn truncate(&mut self, len: usize) {
+        if len >= self.ts.len() {
+            return;
+        }
+        self.payload.truncate(self.len[..len].iter().sum());
+        self.ts.truncate(len);
+        self.slot.truncate(len);
+        self.cycle.truncate(len);
+        self.channel.truncate(len);
+        self.len.truncate(len);
+    }
+}
+
+impl FrameBuffer for FlexRayFrames {
+    fn len(&self) -> usize {
+        self.ts.len()
+    }
+
+    fn clear(&mut self) {
+        self.ts.clear();
+        self.slot.clear();
+        self.cycle.clear();
+        self.channel.clear();
+        self.payload.clear();
+        self.len.clear();
+    }
+
+    fn build(&self, schema: &SchemaRef) -> Result<RecordBatch, ArrowError> {
+        build_flexray_batch(schema, self)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct EthernetFrames {
+    pub ts: Vec<i64>,
+    /// BLF channel minus 1, so --channel 1 is stored as 0
+    pub channel: Vec<u16>,
+    pub source: Vec<String>,
+    pub destination: Vec<String>,
+    pub ethertype: Vec<u16>,
+    pub payload: Vec<Vec<u8>>,
+}
+
+impl EthernetFrames {
+    pub fn push(&mut self, frame: &EthernetFrame) {
+        self.ts.push(frame.ts);
+        self.channel.push(frame.channel.saturating_sub(1));
+        self.source.push(format_mac(&frame.source));
+        self.destination.push(format_mac(&frame.destination));
+        self.ethertype.push(frame.ethertype);
+        self.payload.push(frame.payload.to_vec());
+    }
+
+    pub fn append(&mut self, other: &mut EthernetFrames) {
+        self.ts.append(&mut other.ts);
+        self.channel.append(&mut other.channel);
+        self.source.append(&mut other.source);
+        self.destination.append(&mut other.destination);
+        self.ethertype.append(&mut other.ethertype);
+        self.payload.append(&mut other.payload);
+    }
+
+    /// Keeps the first `len` frames
+    pub fn truncate(&mut self, len: usize) {
+        self.ts.truncate(len);
+        self.channel.truncate(len);
+        self.source.truncate(len);
+        self.destination.truncate(len);
+        self.ethertype.truncate(len);
+        self.payload.truncate(len);
+    }
+}
+
+impl FrameBuffer for EthernetFrames {
+    fn len(&self) -> usize {
+        self.ts.len()
+    }
+
+    fn clear(&mut self) {
+        self.ts.clear();
+        self.channel.clear();
+        self.source.clear();
+        self.destination.clear();
+        self.ethertype.clear();
+        self.payload.clear();
+    }
+
+    fn build(&self, schema: &SchemaRef) -> Result<RecordBatch, ArrowError> {
+        build_ethernet_batch(schema, self)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct LinFrames {
+    pub ts: Vec<i64>,
+    /// BLF channel minus 1, so --channel 1 is stored as 0
+    pub channel: Vec<u16>,
+    pub id: Vec<u8>,
+    pub data: Vec<u8>,
+    pub len: Vec<usize>,
+    pub checksum: Vec<u8>,
+}
+
+impl LinFrames {
+    pub fn push(&mut self, frame: &LinFrame) {
+        self.ts.push(frame.ts);
+        self.channel.push(frame.channel.saturating_sub(1));
+        self.id.push(frame.id);
+        self.data.extend_from_slice(frame.data);
+        self.len.push(frame.data.len());
+        self.checksum.push(frame.checksum);
+    }
+
+    pub fn append(&mut self, other: &mut LinFrames) {
+        self.ts.append(&mut other.ts);
+        self.channel.append(&mut other.channel);
+        self.id.append(&mut other.id);
+        self.data.append(&mut other.data);
+        self.len.append(&mut other.len);
+        self.checksum.append(&mut other.checksum);
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct IsoTpMessages {
+    pub ts_start: Vec<i64>,
+    pub ts_end: Vec<i64>,
+    pub channel: Vec<u16>,
+    pub id: Vec<u32>,
+    pub payload: Vec<Vec<u8>>,
+}
+
+impl IsoTpMessages {
+    pub fn push(&mut self, message: IsoTpMessage) {
+        self.ts_start.push(message.ts_start);
+        self.ts_end.push(message.ts_end);
+        self.channel.push(message.channel);
+        self.id.push(message.id);
+        self.payload.push(message.payload);
+    }
+}
+
+impl FrameBuffer for IsoTpMessages {
+    fn len(&self) -> usize {
+        self.ts_start.len()
+    }
+
+    fn clear(&mut self) {
+        self.ts_start.clear();
+        self.ts_end.clear();
+        self.channel.clear();
+        self.id.clear();
+        self.payload.clear();
+    }
+
+    fn build(&self, schema: &SchemaRef) -> Result<RecordBatch, ArrowError> {
+        RecordBatch::try_new(schema.clone(),
+                             vec![Arc::new(Int64Array::from(self.ts_start.clone())),
+                                  Arc::new(Int64Array::from(self.ts_end.clone())),
+                                  Arc::new(UInt16Array::from(self.channel.clone())),
+                                  Arc::new(UInt32Array::from(self.id.clone())),
+                                  Arc::new(BinaryArray::from_iter_values(self.payload.iter())),
+                                  Arc::new(UInt32Array::from_iter_values(self.payload.iter()
+                                                                             .map(|payload| payload.len() as u32)))])
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ErrorFrames {
+    pub ts: Vec<i64>,
+    /// BLF channel minus 1
+    pub channel: Vec<u16>,
+    pub error_code: Vec<Option<u8>>,
+}
+
+impl ErrorFrames {
+    pub fn push(&mut self, frame: &ErrorFrame) {
+        self.ts.push(frame.ts);
+        self.channel.push(frame.channel.saturating_sub(1));
+        self.error_code.push(frame.error_code);
+    }
+
+    pub fn append(&mut self, other: &mut ErrorFrames) {
+        self.ts.append(&mut other.ts);
+        self.channel.append(&mut other.channel);
+        self.error_code.append(&mut other.error_code);
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct BusStatistics {
+    pub ts: Vec<i64>,
+    /// BLF channel minus 1, as in the frame output
+    pub channel: Vec<u16>,
+    pub bus_load: Vec<f64>,
+    pub std_frames: Vec<u32>,
+    pub ext_frames: Vec<u32>,
+    pub error_frames: Vec<u32>,
+}
+
+impl BusStatistics {
+    pub fn push(&mut self, stat: &BusStatistic) {
+        self.ts.push(stat.ts);
+        self.channel.push(stat.channel.saturating_sub(1));
+        self.bus_load.push(stat.bus_load);
+        self.std_frames.push(stat.std_frames);
+        self.ext_frames.push(stat.ext_frames);
+        self.error_frames.push(stat.error_frames);
+    }
+
+    pub fn append(&mut self, other: &mut BusStatistics) {
+        self.ts.append(&mut other.ts);
+        self.channel.append(&mut other.channel);
+        self.bus_load.append(&mut other.bus_load);
+        self.std_frames.append(&mut other.std_frames);
+        self.ext_frames.append(&mut other.ext_frames);
+        self.error_frames.append(&mut other.error_frames);
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Events {
+    pub ts: Vec<i64>,
+    pub text: Vec<String>,
+}
+
+impl Events {
+    pub fn push(&mut self, event: &Event) {
+        self.ts.push(event.ts);
+        self.text.push(event.text.to_string());
+    }
+
+    pub fn append(&mut self, other: &mut Events) {
+        self.ts.append(&mut other.ts);
+        self.text.append(&mut other.text);
+    }
+}
+
+/// Builds a batch with the columns of `schema` out of the accumulated frames
+pub fn build_record_batch(schema: &SchemaRef, frames: &Frames) -> Result<RecordBatch, ArrowError> {
+    let ts_type = TsType::of(schema);
+
+    let vdata_array = LargeListArray::try_new(
+                          Arc::new(Field::new_list_field(DataType::UInt8, true)),
+                          OffsetBuffer::<i64>::from_lengths(frames.len.iter().copied()),
+                          Arc::new(UInt8Array::from_iter_values(frames.data.iter().copied())), None)?;
+    let mut columns: Vec<ArrayRef> = vec![
+        ts_array(&frames.ts, ts_type),
+        Arc::new(UInt32Array::from_iter_values(frames.id.iter().copied())),
+        Arc::new(vdata_array),
+        Arc::new(UInt8Array::from(frames.dlc.clone())),
+        Arc::new(UInt16Array::from(frames.channel.clone())),
+    ];
+    if schema.column_with_name("rtr").is_some() {
+        columns.push(Arc::new(BooleanArray::from(frames.rtr.clone())));
+    }
+    if schema.column_with_name("extended").is_some() {
+        columns.push(Arc::new(BooleanArray::from(frames.ext.clone())));
+    }
+    if schema.column_with_name("is_fd").is_some() {
+        columns.push(Arc::new(BooleanArray::from(frames.fd.clone())));
+        columns.push(Arc::new(BooleanArray::from(frames.brs.clone())));
+        columns.push(Arc::new(BooleanArray::from(frames.esi.clone())));
+    }
+    if schema.column_with_name("dir").is_some() {
+        columns.push(Arc::new(UInt8Array::from(frames.dir.clone())));
+    }
+    if schema.column_with_name("is_error").is_some() {
+        columns.push(Arc::new(BooleanArray::from(frames.is_error.clone())));
+        columns.push(Arc::new(UInt8Array::from(frames.error_code.clone())));
+    }
+    for values in &frames.signals {
+        columns.push(Arc::new(Float64Array::from(values.clone())));
+    }
+
+    let batch = RecordBatch::try_new(schema.clone(), columns)?;
+
+    // CAN_MESSAGE and CAN_MESSAGE2 objects can be interleaved out of order
+    if !frames.ts.windows(2).all(|w| w[0] <= w[1]) {
+        let indices = sort_to_indices(batch.column(0), None, None)?;
+        return take_record_batch(&batch, &indices);
+    }
+
+    Ok(batch)
+}
+
+pub fn build_flexray_batch(schema: &SchemaRef, frames: &FlexRayFrames) -> Result<RecordBatch, ArrowError> {
+    let ts_type = TsType::of(schema);
+
+    let payload_array = LargeListArray::try_new(
+                            Arc::new(Field::new_list_field(DataType::UInt8, true)),
+                            OffsetBuffer::<i64>::from_lengths(frames.len.iter().copied()),
+                            Arc::new(UInt8Array::from_iter_values(frames.payload.iter().copied())), None)?;
+
+    RecordBatch::try_new(schema.clone(),
+                         vec![ts_array(&frames.ts, ts_type),
+                              Arc::new(UInt16Array::from(frames.slot.clone())),
+                              Arc::new(UInt8Array::from(frames.cycle.clone())),
+                              Arc::new(StringArray::from(frames.channel.clone())),
+                              Arc::new(payload_array)])
+}
+
+pub fn build_ethernet_batch(schema: &SchemaRef, frames: &EthernetFrames) -> Result<RecordBatch, ArrowError> {
+    let ts_type = TsType::of(schema);
+
+    RecordBatch::try_new(schema.clone(),
+                         vec![ts_array(&frames.ts, ts_type),
+                              Arc::new(UInt16Array::from(frames.channel.clone())),
+                              Arc::new(StringArray::from(frames.source.clone())),
+                              Arc::new(StringArray::from(frames.destination.clone())),
+                              Arc::new(UInt16Array::from(frames.ethertype.clone())),
+                              Arc::new(BinaryArray::from_iter_values(frames.payload.iter()))])
+}
+
+pub fn build_lin_batch(schema: &SchemaRef, frames: &LinFrames) -> Result<RecordBatch, ArrowError> {
+    let ts_type = TsType::of(schema);
+
+    let data_array = LargeListArray::try_new(
+                         Arc::new(Field::new_list_field(DataType::UInt8, true)),
+                         OffsetBuffer::<i64>::from_lengths(frames.len.iter().copied()),
+                         Arc::new(UInt8Array::from_iter_values(frames.data.iter().copied())), None)?;
+
+    RecordBatch::try_new(schema.clone(),
+                         vec![ts_array(&frames.ts, ts_type),
+                              Arc::new(UInt16Array::from(frames.channel.clone())),
+                              Arc::new(UInt8Array::from(frames.id.clone())),
+                              Arc::new(data_array),
+                              Arc::new(UInt8Array::from(frames.checksum.clone()))])
+}
+
+pub fn build_error_batch(schema: &SchemaRef, errors: &ErrorFrames) -> Result<RecordBatch, ArrowError> {
+    let ts_type = TsType::of(schema);
+
+    RecordBatch::try_new(schema.clone(),
+                         vec![ts_array(&errors.ts, ts_type),
+                              Arc::new(UInt16Array::from(errors.channel.clone())),
+                              Arc::new(UInt8Array::from(errors.error_code.clone()))])
+}
+
+pub fn build_statistic_batch(schema: &SchemaRef, stats: &BusStatistics) -> Result<RecordBatch, ArrowError> {
+    let ts_type = TsType::of(schema);
+
+    RecordBatch::try_new(schema.clone(),
+                         vec![ts_array(&stats.ts, ts_type),
+                              Arc::new(UInt16Array::from(stats.channel.clone())),
+                              Arc::new(Float64Array::from(stats.bus_load.clone())),
+                              Arc::new(UInt32Array::from(stats.std_frames.clone())),
+                              Arc::new(UInt32Array::from(stats.ext_frames.clone())),
+                              Arc::new(UInt32Array::from(stats.error_frames.clone()))])
+}
+
+pub fn build_event_batch(schema: &SchemaRef, events: &Events) -> Result<RecordBatch, ArrowError> {
+    let ts_type = TsType::of(schema);
+
+    RecordBatch::try_new(schema.clone(),
+                         vec![ts_array(&events.ts, ts_type),
+                              Arc::new(StringArray::from(events.text.clone()))])
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Codec {
+    #[default]
+    Snappy,
+    Zstd,
+    Lz4,
+    Gzip,
+    Brotli,
+    None,
+}
+
+impl Codec {
+    /// Parquet codec at `level`, or at the default level when None.
+    /// A level is an error for codecs without one (snappy, lz4, none) and when out of the codec range.
+    pub fn compression(self, level: Option<u32>) -> Result<Compression, ParquetError> {
+        if let Some(level) = level && matches!(self, Codec::Snappy | Codec::Lz4 | Codec::None) {
+            let name = self.to_possible_value().map_or(String::new(), |value| value.get_name().to_string());
+            return Err(ParquetError::General(format!("{} compression has no level, {} is not allowed", name, level)));
+        }
+        let level_error = |error: ParquetError| ParquetError::General(format!("Invalid compression level: {}", error));
+        Ok(match self {
+            Codec::Snappy => Compression::SNAPPY,
+            Codec::Zstd => Compression::ZSTD(match level {
+                Some(level) => ZstdLevel::try_new(i32::try_from(level).unwrap_or(i32::MAX)).map_err(level_error)?,
+                None => ZstdLevel::default(),
+            }),
+            Codec::Lz4 => Compression::LZ4_RAW,
+            Codec::Gzip => Compression::GZIP(match level {
+                Some(level) => GzipLevel::try_new(level).map_err(level_error)?,
+                None => GzipLevel::default(),
+            }),
+            Codec::Brotli => Compression::BROTLI(match level {
+                Some(level) => BrotliLevel::try_new(level).map_err(level_error)?,
+                None => BrotliLevel::default(),
+            }),
+            Codec::None => Compression::UNCOMPRESSED,
+        })
+    }
+}
+
+/// Columns with a few values repeated over the whole capture
+const DICTIONARY_COLUMNS: [&str; 3] = ["id", "channel", "dlc"];
+
+/// Without `dictionary` no column is dictionary encoded, to compare file sizes. Batches larger than
+/// `row_group_size` rows are split into several row groups.
+pub fn writer_properties(codec: Codec, level: Option<u32>, dictionary: bool, row_group_size: usize)
+    -> Result<WriterProperties, ParquetError>
+{
+    let mut builder = WriterProperties::builder().set_compression(codec.compression(level)?)
+                                                 .set_dictionary_enabled(dictionary)
+                                                 .set_max_row_group_size(row_group_size);
+    for column in DICTIONARY_COLUMNS {
+        builder = builder.set_column_dictionary_enabled(ColumnPath::from(column), dictionary);
+    }
+    Ok(builder.build())
+}
+
+pub fn new_writer(file: File, schema: SchemaRef, codec: Codec, level: Option<u32>, dictionary: bool,
+                  row_group_size: usize)
+    -> Result<ArrowWriter<File>, ParquetError>
+{
+    ArrowWriter::try_new(file, schema, Some(writer_properties(codec, level, dictionary, row_group_size)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use parquet::{
+        basic::Encoding,
+        file::reader::{FileReader, SerializedFileReader},
+    };
+    use super::*;
+
+    /// Encodings of each column chunk of a file written with `dictionary`, by column name
+    fn column_encodings(dictionary: bool) -> Vec<(String, Vec<Encoding>)> {
+        let mut frames = Frames::default();
+        for index in 0..100u8 {
+            frames.push(&Frame {
+                ts: 1_718_445_005_000_000_000 + i64::from(index) * 1_000_000,
+                channel: 1 + u16::from(index % 2),
+                id: 0x100 + u32::from(index % 4),
+                ext: false,
+                data: &[index; 8],
+                dlc: 8,
+                rtr: false,
+                fd: false,
+                brs: false,
+                esi: false,
+                dir: 0,
+            });
+        }
+        let schema = frame_schema(ExtraColumns::default(), TsType::Int64);
+        let path = std::env::temp_dir().join(format!("parquet2peak-{}-dictionary-{}.parquet", std::process::id(),
+                                                     dictionary));
+        let mut writer = new_writer(File::create(&path).unwrap(), schema.clone(), Codec::Snappy, None, dictionary,
+                                    1024).unwrap();
+        writer.write(&build_record_batch(&schema, &frames).unwrap()).unwrap();
+        writer.close().unwrap();
+
+        let reader = SerializedFileReader::new(File::open(&path).unwrap()).unwrap();
+        let row_group = reader.metadata().row_group(0);
+        let encodings = row_group.columns().iter()
+                                 .map(|column| (column.column_path().string(), column.encodings().clone()))
+                                 .collect();
+        std::fs::remove_file(&path).unwrap();
+        encodings
+    }
+
+    fn is_dictionary(encoding: &Encoding) -> bool {
+        matches!(encoding, Encoding::RLE_DICTIONARY | Encoding::PLAIN_DICTIONARY)
+    }
+
+    #[test]
+    fn dictionary_columns() {
+        let columns = column_encodings(true);
+        for column in DICTIONARY_COLUMNS {
+            let (_, encodings) = columns.iter().find(|(name, _)| name == column).unwrap();
+            assert!(encodings.iter().any(is_dictionary), "{} is not dictionary encoded: {:?}", column, encodings);
+        }
+    }
+
+    #[test]
+    fn no_dictionary() {
+        for (column, encodings) in column_encodings(false) {
+            assert!(!encodings.iter().any(is_dictionary), "{} is dictionary encoded: {:?}", column, encodings);
+        }
+    }
+}
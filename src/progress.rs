@@ -0,0 +1,26 @@
+//! Progress bar shared by the conversion and the replay
+
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+
+/// Redraws per second, a slow terminal is not flooded by fast frame rates
+const DRAW_HZ: u8 = 25;
+
+/// Bar of `len` items with elapsed time, rate and ETA, hidden when `visible` is false
+pub fn progress_bar(len: u64, visible: bool) -> ProgressBar {
+    if !visible {
+        return ProgressBar::hidden();
+    }
+    let style = ProgressStyle::with_template("[{elapsed_precise}] {wide_bar} {pos}/{len} {per_sec} ETA {eta} {msg}")
+                    .unwrap_or_else(|_| ProgressStyle::default_bar());
+    ProgressBar::new(len).with_style(style)
+}
+
+/// Replay bar with percentage, the loop in the prefix and the ETA of the frame schedule as message
+pub fn replay_bar(len: u64, visible: bool) -> ProgressBar {
+    if !visible {
+        return ProgressBar::hidden();
+    }
+    let template = "[{elapsed_precise}] {prefix} {wide_bar} {percent}% {pos}/{len} {per_sec} {msg}";
+    let style = ProgressStyle::with_template(template).unwrap_or_else(|_| ProgressStyle::default_bar());
+    ProgressBar::with_draw_target(Some(len), ProgressDrawTarget::stderr_with_hz(DRAW_HZ)).with_style(style)
+}
@@ -0,0 +1,594 @@
+//! Loading of converted parquet rows and their replay on a CAN bus
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::sleep,
+    time::{Duration, Instant},
+};
+use indicatif::{HumanDuration, ProgressBar};
+use parquet::{
+    basic::{ConvertedType, LogicalType, Type as PhysicalType},
+    file::reader::{ChunkReader, FileReader, SerializedFileReader},
+    schema::types::{SchemaDescriptor, Type},
+    record::{Field, Row, RowAccessor},
+    errors::ParquetError,
+};
+use clap::ValueEnum;
+use peak_can::socket::Baudrate;
+use crate::{
+    error::Error,
+    ids::id_allowed,
+    progress::replay_bar,
+    sink::{CanSink, SendError},
+};
+
+/// Supported bus speeds in kbit/s
+pub const BAUDRATES: [(u32, Baudrate); 4] = [
+    (125, Baudrate::Baud125K),
+    (250, Baudrate::Baud250K),
+    (500, Baudrate::Baud500K),
+    (1000, Baudrate::Baud1M),
+];
+
+/// Frames kept by direction, as logged by the node that recorded the BLF
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Direction {
+    Rx,
+    Tx,
+    #[default]
+    Both,
+}
+
+/// Frame loaded from a converted parquet file
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplayFrame {
+    /// Seconds since Unix epoch
+    pub ts: f64,
+    /// Nanoseconds since Unix epoch, exact for Int64 columns, rounded from the seconds of Float64 ones
+    pub ts_ns: i64,
+    pub id: u32,
+    pub data: Vec<u8>,
+    /// None for files written before the dlc column existed
+    pub dlc: Option<u8>,
+    /// Remote frame, false for files written before the rtr column existed
+    pub rtr: bool,
+    /// 29-bit identifier, None for files written before the extended column existed
+    pub extended: Option<bool>,
+    /// 0 Rx, 1 Tx, None for files written before the dir column existed
+    pub dir: Option<u8>,
+    /// None for files written before the channel column existed
+    pub channel: Option<u16>,
+    /// Error frame row of blf2parquet --error-rows, false for files without is_error column
+    pub is_error: bool,
+}
+
+impl ReplayFrame {
+    /// Extended flag of the BLF, or IDs from 0x800 when the file has no extended column
+    pub fn is_extended(&self) -> bool {
+        self.extended.unwrap_or(self.id >= 0x800)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ReplayOptions {
+    /// IDs dropped while loading
+    pub exclude_id: Vec<u32>,
+    /// When not empty, only these IDs are loaded, before `exclude_id` is applied
+    pub include_id: Vec<u32>,
+    /// Logged ID to replayed ID, applied after the ID filters
+    pub remap_id: HashMap<u32, u32>,
+    /// Channel column value to replay, the BLF channel minus 1, every channel when None
+    pub channel: Option<u16>,
+    /// Ignored for files without dir column
+    pub direction: Direction,
+    /// Load the error frame rows too, sent with the ID and data they recorded
+    pub replay_errors: bool,
+    /// Times the frames are sent, 0 repeats until an error
+    pub loop_count: u32,
+    /// Inter-frame delays are divided by this factor, must be > 0
+    pub speed_factor: f64,
+    /// Seconds added to every timestamp before the delays are computed
+    pub offset_time: f64,
+    /// Longest wait between two frames, applied after `speed_factor`, unlimited when None
+    pub max_gap: Option<Duration>,
+    /// Shortest wait between two frames, applied after `max_gap` for controllers failing on back-to-back frames
+    pub min_gap: Duration,
+    /// Most frames per second of an ID as sent, after `remap_id`, the frames above it are skipped
+    pub rate_limit: HashMap<u32, u32>,
+    /// Sends repeated with a backoff when the transmit queue is full, before the replay stops as on a bus error
+    pub max_send_retries: u32,
+    /// Draw a progress bar over the sent frames
+    pub progress: bool,
+    /// Set from another thread (eg: a signal handler) to stop the replay, checked every `STOP_CHECK_FRAMES` frames
+    pub stop: Arc<AtomicBool>,
+}
+
+impl Default for ReplayOptions {
+    fn default() -> Self {
+        ReplayOptions {
+            exclude_id: Vec::new(),
+            include_id: Vec::new(),
+            remap_id: HashMap::new(),
+            channel: None,
+            direction: Direction::Both,
+            replay_errors: false,
+            loop_count: 1,
+            speed_factor: 1.0,
+            offset_time: 0.0,
+            max_gap: None,
+            min_gap: Duration::ZERO,
+            rate_limit: HashMap::new(),
+            max_send_retries: 3,
+            progress: false,
+            stop: Arc::new(AtomicBool::new(false)),
+        }
+    }
+}
+
+/// Timing summary of the frames to replay
+#[derive(Debug, Clone, Default)]
+pub struct ReplayStats {
+    pub frames: usize,
+    /// Seconds between first and last frame
+    pub span: f64,
+    /// Inter-frame gaps in seconds, None with less than two frames
+    pub min_gap: Option<f64>,
+    pub max_gap: Option<f64>,
+    pub mean_gap: Option<f64>,
+    /// Frames per ID
+    pub ids: BTreeMap<u32, usize>,
+}
+
+pub fn replay_stats(content: &[ReplayFrame]) -> ReplayStats {
+    let mut stats = ReplayStats {
+        frames: content.len(),
+        ..Default::default()
+    };
+    for frame in content {
+        *stats.ids.entry(frame.id).or_insert(0) += 1;
+    }
+
+    if let (Some(first), Some(last)) = (content.first(), content.last()) {
+        stats.span = last.ts - first.ts;
+    }
+
+    let gaps: Vec<f64> = content.windows(2).map(|w| w[1].ts - w[0].ts).collect();
+    if !gaps.is_empty() {
+        stats.min_gap = gaps.iter().copied().reduce(f64::min);
+        stats.max_gap = gaps.iter().copied().reduce(f64::max);
+        stats.mean_gap = Some(gaps.iter().sum::<f64>() / gaps.len() as f64);
+    }
+
+    stats
+}
+
+/// Consecutive frames whose timestamp decreases, their delay is lost at replay
+pub fn out_of_order(content: &[ReplayFrame]) -> usize {
+    content.windows(2).filter(|w| w[1].ts < w[0].ts).count()
+}
+
+/// Columns `process_row` reads by position, with the types it accepts
+const EXPECTED_COLUMNS: &str = "ts: Int64, Timestamp or Float64 (seconds), id: UInt32, data: List<UInt8>";
+
+/// Short name of a column type, the element type for lists
+fn type_name(field: &Type) -> String {
+    let info = field.get_basic_info();
+    if !field.is_primitive() {
+        let list = info.logical_type() == Some(LogicalType::List) || info.converted_type() == ConvertedType::LIST;
+        return match field.get_fields().first() {
+            Some(element) if list => format!("List<{}>", type_name(leaf(element))),
+            _ => "Struct".to_string(),
+        };
+    }
+    match info.converted_type() {
+        ConvertedType::UINT_8 => return "UInt8".to_string(),
+        ConvertedType::UINT_32 => return "UInt32".to_string(),
+        _ => {}
+    }
+    match (field.get_physical_type(), info.logical_type()) {
+        (_, Some(LogicalType::Integer { bit_width, is_signed })) => {
+            format!("{}Int{}", if is_signed { "" } else { "U" }, bit_width)
+        }
+        (_, Some(LogicalType::Timestamp { .. })) => "Timestamp".to_string(),
+        (_, Some(LogicalType::String)) => "Utf8".to_string(),
+        (PhysicalType::BOOLEAN, _) => "Boolean".to_string(),
+        (PhysicalType::INT32, _) => "Int32".to_string(),
+        (PhysicalType::INT64, _) => "Int64".to_string(),
+        (PhysicalType::FLOAT, _) => "Float32".to_string(),
+        (PhysicalType::DOUBLE, _) => "Float64".to_string(),
+        (physical, _) => physical.to_string(),
+    }
+}
+
+/// First primitive column under `field`, the element of a list
+fn leaf(field: &Type) -> &Type {
+    if field.is_primitive() {
+        return field;
+    }
+    field.get_fields().first().map_or(field, |child| leaf(child))
+}
+
+/// Checks the names and types of the columns `process_row` reads by position, the optional ones are looked up by
+/// name. The error lists the expected columns and the ones of the file.
+pub fn check_schema(schema: &SchemaDescriptor) -> Result<(), ParquetError> {
+    let fields = schema.root_schema().get_fields();
+    let column_is = |index: usize, name: &str, types: &[&str]| {
+        fields.get(index).is_some_and(|field| field.name() == name && types.contains(&type_name(field).as_str()))
+    };
+
+    if column_is(0, "ts", &["Int64", "Timestamp", "Float64"]) && column_is(1, "id", &["UInt32"])
+        && column_is(2, "data", &["List<UInt8>"])
+    {
+        return Ok(());
+    }
+    let found = fields.iter()
+                      .map(|field| format!("{}: {}", field.name(), type_name(field)))
+                      .collect::<Vec<_>>()
+                      .join(", ");
+    Err(ParquetError::General(format!("Not a converted frame file, the first columns must be\n  {}\nfound\n  {}",
+                                      EXPECTED_COLUMNS, if found.is_empty() { "no column" } else { &found })))
+}
+
+pub fn process_row(row: &Row) -> Result<ReplayFrame, ParquetError> {
+    let mut data = Vec::new();
+
+    // Int64 nanoseconds, Timestamp(Nanosecond) which has no converted type and is read back as a long too, or
+    // Float64 seconds for files written with --legacy-float-ts
+    let (timing, ts_ns) = match row.get_long(0) {
+        Ok(ns) => (ns as f64 / 1e9, ns),
+        Err(_) => {
+            let seconds = row.get_double(0)?;
+            (seconds, (seconds * 1e9).round() as i64)
+        }
+    };
+    let id = row.get_uint(1)?;
+    if let Ok(list) = row.get_list(2) {
+        for f in list.elements().iter() {
+            if let Field::UByte(value) = f {
+                data.push(*value);
+            }
+        }
+    }
+
+    let dlc = row.get_column_iter()
+                 .find(|(name, _)| name.as_str() == "dlc")
+                 .and_then(|(_, field)| match field {
+                     Field::UByte(value) => Some(*value),
+                     _ => None,
+                 });
+
+    let rtr = row.get_column_iter()
+                 .find(|(name, _)| name.as_str() == "rtr")
+                 .is_some_and(|(_, field)| matches!(field, Field::Bool(true)));
+
+    let extended = row.get_column_iter()
+                      .find(|(name, _)| name.as_str() == "extended")
+                      .and_then(|(_, field)| match field {
+                          Field::Bool(value) => Some(*value),
+                          _ => None,
+                      });
+
+    let dir = row.get_column_iter()
+                 .find(|(name, _)| name.as_str() == "dir")
+                 .and_then(|(_, field)| match field {
+                     Field::UByte(value) => Some(*value),
+                     _ => None,
+                 });
+
+    let channel = row.get_column_iter()
+                     .find(|(name, _)| name.as_str() == "channel")
+                     .and_then(|(_, field)| match field {
+                         Field::UByte(value) => Some(u16::from(*value)),
+                         Field::UShort(value) => Some(*value),
+                         _ => None,
+                     });
+
+    let is_error = row.get_column_iter()
+                      .find(|(name, _)| name.as_str() == "is_error")
+                      .is_some_and(|(_, field)| matches!(field, Field::Bool(true)));
+
+    Ok(ReplayFrame { ts: timing, ts_ns, id, data, dlc, rtr, extended, dir, channel, is_error })
+}
+
+fn direction_match(dir: Option<u8>, direction: Direction) -> bool {
+    match (direction, dir) {
+        (Direction::Both, _) | (_, None) => true,
+        (Direction::Rx, Some(dir)) => dir == 0,
+        // LIN Tx requests count as Tx
+        (Direction::Tx, Some(dir)) => dir != 0,
+    }
+}
+
+/// Loads the frames to replay, returns them with the number of rows read
+pub fn load_parquet<R: ChunkReader + 'static>(reader: R, opts: &ReplayOptions)
+    -> Result<(Vec<ReplayFrame>, usize), Error>
+{
+    let reader = SerializedFileReader::new(reader).map_err(Error::OpenParquet)?;
+    check_schema(reader.metadata().file_metadata().schema_descr())?;
+
+    let mut row_iter = reader.get_row_iter(None)?;
+
+    let mut content: Vec<ReplayFrame> = Vec::new();
+    let mut elem = 0;
+
+    while let Some(Ok(row)) = row_iter.next() {
+        if let Ok(mut frame) = process_row(&row) {
+            // files without the channel column are replayed entirely
+            let channel_match = match (opts.channel, frame.channel) {
+                (Some(wanted), Some(channel)) => wanted == channel,
+                _ => true,
+            };
+            if channel_match && direction_match(frame.dir, opts.direction) && (!frame.is_error || opts.replay_errors)
+               && id_allowed(frame.id, &opts.include_id, &opts.exclude_id) {
+                if let Some(id) = opts.remap_id.get(&frame.id) {
+                    frame.id = *id;
+                    // a new ID that does not fit in 11 bits can only be sent as extended
+                    frame.extended = frame.extended.map(|extended| extended || *id >= 0x800);
+                }
+                content.push(frame);
+            }
+        }
+        elem += 1;
+    }
+
+    Ok((content, elem))
+}
+
+/// ETA refresh period of the replay bar
+const ETA_INTERVAL: Duration = Duration::from_millis(40);
+
+/// Wait in nanoseconds between frames at `previous` and `curr` seconds
+fn gap_ns(previous: f64, curr: f64, speed_factor: f64, max_gap: Option<Duration>, min_gap: Duration) -> f64 {
+    // negative deltas are sent without waiting, unless `min_gap` is set
+    let gap_ns = (curr - previous).max(0.0) * 1_000_000_000.0 / speed_factor;
+    let gap_ns = max_gap.map_or(gap_ns, |max_gap| gap_ns.min(max_gap.as_nanos() as f64));
+    gap_ns.max(min_gap.as_nanos() as f64)
+}
+
+/// Frames sent between two checks of `ReplayOptions::stop`
+const STOP_CHECK_FRAMES: usize = 100;
+/// Longest sleep before `ReplayOptions::stop` is checked again, for the idle gaps of a log
+const STOP_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Sleeps `duration` in slices, returns false when `stop` is set meanwhile
+fn wait(duration: Duration, stop: &AtomicBool) -> bool {
+    let end = Instant::now() + duration;
+    loop {
+        let left = end.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return true;
+        }
+        if stop.load(Ordering::Relaxed) {
+            return false;
+        }
+        sleep(left.min(STOP_CHECK_INTERVAL));
+    }
+}
+
+/// First wait before a frame refused by a full transmit queue is sent again, doubled on each retry
+const SEND_RETRY_BACKOFF: Duration = Duration::from_micros(100);
+/// Doublings of `SEND_RETRY_BACKOFF`, the longest wait is 6.4 ms
+const SEND_RETRY_MAX_DOUBLINGS: u32 = 6;
+
+/// Kept across the loops: the last send time of the IDs of `ReplayOptions::rate_limit` and the counts of the
+/// summary
+#[derive(Debug, Default)]
+pub struct SendState {
+    last_sent: HashMap<u32, Instant>,
+    /// Frames skipped by the rate limit since the replay started
+    pub skipped: usize,
+    /// Sends repeated after a full transmit queue since the replay started
+    pub retries: usize,
+}
+
+impl SendState {
+    /// True when a frame of `id` was sent less than 1/fps before `now`, the frame is then counted as skipped
+    fn skip(&mut self, id: u32, now: Instant, limits: &HashMap<u32, u32>) -> bool {
+        let Some(fps) = limits.get(&id) else {
+            return false;
+        };
+        let period = Duration::from_micros(1_000_000 / u64::from(*fps).max(1));
+        if self.last_sent.get(&id).is_some_and(|last| now.duration_since(*last) < period) {
+            self.skipped += 1;
+            return true;
+        }
+        self.last_sent.insert(id, now);
+        false
+    }
+}
+
+/// Sends `content` once with its timestamps shifted by `ReplayOptions::offset_time` seconds and the waits capped to
+/// `max_gap`, never shorter than `min_gap` between two frames. Frames above the `rate_limit` of their ID are skipped
+/// and counted in `state`, as the retries of the frames refused by a full transmit queue. `bar` is advanced for each
+/// frame. Returns false when `stop` was set before the last frame.
+pub fn send_can_messages(content: &[ReplayFrame], sink: &dyn CanSink, opts: &ReplayOptions,
+                         state: &mut SendState, bar: &ProgressBar)
+    -> Result<bool, SendError>
+{
+    let &ReplayOptions { speed_factor, offset_time, max_gap, min_gap, ref stop, .. } = opts;
+    let mut old_timing: Option<f64> = None;
+    let mut passive_timing = Duration::new(0, 0);
+    // time spent beyond the scaled gaps, recovered on the next frames
+    let mut lag_ns = 0.0;
+    // the ETA follows the schedule, the send rate is meaningless across idle gaps
+    let mut remaining_ns: f64 = if bar.is_hidden() {
+        0.0
+    } else {
+        content.windows(2).map(|pair| gap_ns(pair[0].ts, pair[1].ts, speed_factor, max_gap, min_gap)).sum()
+    };
+    let mut eta_drawn: Option<Instant> = None;
+
+    for (index, frame) in content.iter().enumerate() {
+        if index % STOP_CHECK_FRAMES == 0 && stop.load(Ordering::Relaxed) {
+            return Ok(false);
+        }
+        let curr = frame.ts + offset_time;
+        if let Some(previous) = old_timing {
+            let gap = gap_ns(previous, curr, speed_factor, max_gap, min_gap);
+            remaining_ns -= gap;
+            let diff = gap - (passive_timing.as_nanos() as f64) - lag_ns;
+            lag_ns = (-diff).max(0.0);
+            // the lag recovery cannot bring two frames closer than `min_gap`
+            let udiff = ((diff.max(0.0) / 1_000.0) as u64).max(min_gap.as_micros() as u64);
+            if udiff > 0 && !wait(Duration::from_micros(udiff), stop) {
+                return Ok(false);
+            }
+            //println!("Waiting {}us", udiff);
+        }
+
+        let start = Instant::now();
+        old_timing = Some(curr);
+        if state.skip(frame.id, start, &opts.rate_limit) {
+            bar.inc(1);
+            passive_timing = start.elapsed();
+            continue;
+        }
+
+        let mut attempt = 0;
+        let sent = loop {
+            match sink.send(frame) {
+                Err(SendError::Busy(_)) if attempt < opts.max_send_retries => {
+                    sleep(SEND_RETRY_BACKOFF * 2u32.pow(attempt.min(SEND_RETRY_MAX_DOUBLINGS)));
+                    attempt += 1;
+                    state.retries += 1;
+                }
+                result => break result,
+            }
+        };
+        match sent {
+            Ok(()) => {}
+            Err(SendError::Bus(err) | SendError::Busy(err)) => {
+                bar.suspend(|| eprintln!("Error {}", err));
+                break;
+            }
+            Err(err) => return Err(err),
+        }
+
+        bar.inc(1);
+        if !bar.is_hidden() && eta_drawn.is_none_or(|drawn| start.duration_since(drawn) >= ETA_INTERVAL) {
+            let eta = Duration::from_nanos(remaining_ns.max(0.0) as u64);
+            bar.set_message(format!("ETA {}", HumanDuration(eta)));
+            eta_drawn = Some(start);
+        }
+        passive_timing = start.elapsed();
+    }
+
+    Ok(true)
+}
+
+/// Sends `content` `loop_count` times, or until an error when it is 0, `ReplayOptions::stop` ends any loop
+pub fn replay(content: &[ReplayFrame], sink: &dyn CanSink, opts: &ReplayOptions) -> Result<(), SendError> {
+    let bar = replay_bar(content.len() as u64, opts.progress);
+    let mut remaining = opts.loop_count;
+    let mut iteration = 1;
+    let mut state = SendState::default();
+    loop {
+        match opts.loop_count {
+            0 => bar.set_prefix(format!("loop {}", iteration)),
+            1 => {}
+            count => bar.set_prefix(format!("loop {}/{}", iteration, count)),
+        }
+        if !send_can_messages(content, sink, opts, &mut state, &bar)? {
+            let sent = bar.position();
+            bar.abandon();
+            eprintln!("Stopped in loop {}: {} of {} frames sent ({:.1}%)", iteration, sent, content.len(),
+                      sent as f64 * 100.0 / content.len().max(1) as f64);
+            print_send_summary(&state, opts);
+            return Ok(());
+        }
+        if remaining > 0 {
+            remaining -= 1;
+            if remaining == 0 {
+                break;
+            }
+        }
+        bar.println("Restarting...");
+        bar.reset();
+        iteration += 1;
+    }
+    bar.finish_with_message(format!("{} frames sent", bar.position()));
+    print_send_summary(&state, opts);
+
+    Ok(())
+}
+
+fn print_send_summary(state: &SendState, opts: &ReplayOptions) {
+    if !opts.rate_limit.is_empty() {
+        println!("Rate limited: {} frames skipped", state.skipped);
+    }
+    if state.retries > 0 {
+        println!("Send retries: {} (transmit queue full)", state.retries);
+    }
+}
+
+/// Loads the parquet read from `reader` and replays it on `sink`
+pub fn replay_parquet<R: ChunkReader + 'static>(reader: R, sink: &dyn CanSink, opts: &ReplayOptions)
+    -> Result<(), Error>
+{
+    let (content, _) = load_parquet(reader, opts)?;
+    replay(&content, sink, opts)?;
+
+    Ok(())
+}
+
+/// Accepts kbit/s values ("250") or suffixed ones ("250k", "1m")
+pub fn parse_baudrate(input: &str) -> Option<Baudrate> {
+    let input = input.trim().to_lowercase();
+    let kbps = if let Some(value) = input.strip_suffix('k') {
+        value.parse::<u32>().ok()?
+    } else if let Some(value) = input.strip_suffix('m') {
+        value.parse::<u32>().ok()?.checked_mul(1000)?
+    } else {
+        input.parse::<u32>().ok()?
+    };
+    // Baudrate is not Copy, the const gives an owned array
+    BAUDRATES.into_iter()
+             .find(|(rate, _)| *rate == kbps)
+             .map(|(_, baudrate)| baudrate)
+}
+
+fn parse_hex(input: &str) -> Option<u32> {
+    let input = input.trim();
+    let hex = input.strip_prefix("0x").or_else(|| input.strip_prefix("0X")).unwrap_or(input);
+    u32::from_str_radix(hex, 16).ok()
+}
+
+/// Parses `original_id,new_id` lines of hex IDs, the header line is optional
+pub fn parse_id_map(input: &str) -> Result<HashMap<u32, u32>, Error> {
+    let mut map = HashMap::new();
+    for (index, line) in input.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || (index == 0 && line.eq_ignore_ascii_case("original_id,new_id")) {
+            continue;
+        }
+        let ids = line.split_once(',').and_then(|(original, new)| Some((parse_hex(original)?, parse_hex(new)?)));
+        let Some((original, new)) = ids else {
+            return Err(Error::Options(format!("Invalid ID mapping on line {}: {:?}", index + 1, line)));
+        };
+        map.insert(original, new);
+    }
+
+    Ok(map)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn min_gap_applies_to_back_to_back_frames() {
+        let min_gap = Duration::from_micros(100);
+        // same timestamp, or going back
+        assert_eq!(gap_ns(1.0, 1.0, 1.0, None, min_gap), 100_000.0);
+        assert_eq!(gap_ns(1.0, 0.5, 1.0, None, min_gap), 100_000.0);
+        // longer gaps are kept, then scaled and capped before the minimum applies
+        assert_eq!(gap_ns(1.0, 1.5, 1.0, None, min_gap), 500_000_000.0);
+        assert_eq!(gap_ns(1.0, 1.5, 10_000.0, None, min_gap), 100_000.0);
+        assert_eq!(gap_ns(1.0, 2.0, 1.0, Some(Duration::from_micros(10)), min_gap), 100_000.0);
+        assert_eq!(gap_ns(1.0, 1.0, 1.0, None, Duration::ZERO), 0.0);
+    }
+}
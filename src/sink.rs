@@ -0,0 +1,103 @@
+//! Replay targets: PEAK USB adapters and, on Linux, SocketCAN interfaces
+
+use clap::ValueEnum;
+use peak_can::{
+    error::CanError,
+    socket::{CanFrame, MessageType, SendCan, usb::UsbCanSocket},
+};
+use crate::replay::ReplayFrame;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Backend {
+    #[default]
+    Peak,
+    /// Linux only, the bitrate is set on the interface (ip link)
+    #[value(name = "socketcan")]
+    SocketCan,
+}
+
+#[derive(Debug)]
+pub enum SendError {
+    /// The frame cannot be built from the row, replay is aborted
+    Frame(String),
+    /// The bus refused the frame
+    Bus(String),
+    /// The transmit queue of the adapter is full, the frame can be sent again
+    Busy(String),
+}
+
+/// Bus the replayed frames are written to
+pub trait CanSink {
+    fn send(&self, frame: &ReplayFrame) -> Result<(), SendError>;
+}
+
+impl CanSink for UsbCanSocket {
+    fn send(&self, frame: &ReplayFrame) -> Result<(), SendError> {
+        // peak-can only builds data frames
+        if frame.rtr {
+            return Err(SendError::Frame(format!("remote frame 0x{:X} cannot be sent through PEAK, use --backend \
+                                                 socketcan", frame.id)));
+        }
+        let t = if frame.is_extended() {
+            MessageType::Extended
+        } else {
+            MessageType::Standard
+        };
+
+        let can_frame = CanFrame::new(frame.id, t, &frame.data)
+                            .map_err(|err| SendError::Frame(format!("{:?}", err)))?;
+
+        SendCan::send(self, can_frame).map_err(|err| {
+            let message = format!("{:?}: unable to send frame {:?}", err, can_frame);
+            match err {
+                CanError::XmtFull | CanError::QxmtFull => SendError::Busy(message),
+                _ => SendError::Bus(message),
+            }
+        })
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub use socketcan_sink::open_socketcan;
+
+#[cfg(target_os = "linux")]
+mod socketcan_sink {
+    use std::io::ErrorKind;
+    use socketcan::{CanFrame, CanSocket, EmbeddedFrame, ExtendedId, Id, Socket, StandardId};
+    use super::{CanSink, SendError};
+    use crate::replay::ReplayFrame;
+
+    /// Linux errno of a full transmit queue
+    const ENOBUFS: i32 = 105;
+
+    pub fn open_socketcan(interface: &str) -> std::io::Result<CanSocket> {
+        CanSocket::open(interface)
+    }
+
+    impl CanSink for CanSocket {
+        fn send(&self, frame: &ReplayFrame) -> Result<(), SendError> {
+            let id = if frame.is_extended() {
+                ExtendedId::new(frame.id).map(Id::Extended)
+            } else {
+                // the cast to u16 would wrap an ID above 0xFFFF into a valid one
+                u16::try_from(frame.id).ok().and_then(StandardId::new).map(Id::Standard)
+            }.ok_or_else(|| SendError::Frame(format!("invalid id 0x{:X}", frame.id)))?;
+
+            let can_frame = if frame.rtr {
+                let dlc = frame.dlc.map_or(frame.data.len(), usize::from);
+                CanFrame::new_remote(id, dlc)
+            } else {
+                CanFrame::new(id, &frame.data)
+            }.ok_or_else(|| SendError::Frame(format!("invalid data length {}", frame.data.len())))?;
+
+            self.write_frame(&can_frame).map_err(|err| {
+                let message = format!("{:?}: unable to send frame {:?}", err, can_frame);
+                if err.kind() == ErrorKind::WouldBlock || err.raw_os_error() == Some(ENOBUFS) {
+                    SendError::Busy(message)
+                } else {
+                    SendError::Bus(message)
+                }
+            })
+        }
+    }
+}